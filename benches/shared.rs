@@ -0,0 +1,111 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use shared::{shared, PackedShared, Shared};
+
+fn new(c: &mut Criterion) {
+    let mut group = c.benchmark_group("new");
+    group.bench_function("shared", |b| b.iter(|| Shared::new(black_box(1usize))));
+    group.bench_function("packed", |b| b.iter(|| PackedShared::new_in(black_box(1usize))));
+    group.bench_function("rc_refcell", |b| b.iter(|| Rc::new(RefCell::new(black_box(1usize)))));
+    group.bench_function("box", |b| b.iter(|| Box::new(black_box(1usize))));
+    group.bench_function("value", |b| b.iter(|| black_box(1usize)));
+    group.finish();
+}
+
+fn clone(c: &mut Criterion) {
+    let shared = Shared::new(1usize);
+    let packed = PackedShared::new_in(1usize);
+    let rc = Rc::new(RefCell::new(1usize));
+    let boxed = Box::new(1usize);
+    let value = 1usize;
+
+    let mut group = c.benchmark_group("clone");
+    group.bench_function("shared", |b| b.iter(|| black_box(&shared).clone()));
+    group.bench_function("packed", |b| b.iter(|| black_box(&packed).clone()));
+    group.bench_function("rc_refcell", |b| b.iter(|| black_box(&rc).clone()));
+    group.bench_function("box", |b| b.iter(|| black_box(&boxed).clone()));
+    group.bench_function("value", |b| b.iter(|| *black_box(&value)));
+    group.finish();
+}
+
+fn deref(c: &mut Criterion) {
+    let shared = Shared::new(1usize);
+    let packed = PackedShared::new_in(1usize);
+    let rc = Rc::new(RefCell::new(1usize));
+    let boxed = Box::new(1usize);
+    let value = 1usize;
+
+    let mut group = c.benchmark_group("deref");
+    group.bench_function("shared", |b| b.iter(|| **black_box(&shared)));
+    group.bench_function("shared_borrow", |b| b.iter(|| *black_box(&shared).borrow()));
+    group.bench_function("packed", |b| b.iter(|| **black_box(&packed)));
+    group.bench_function("rc_refcell", |b| b.iter(|| *black_box(&rc).borrow()));
+    group.bench_function("box", |b| b.iter(|| **black_box(&boxed)));
+    group.bench_function("value", |b| b.iter(|| *black_box(&value)));
+    group.finish();
+}
+
+fn deref_mut(c: &mut Criterion) {
+    let mut shared = Shared::new(1usize);
+    let mut packed = PackedShared::new_in(1usize);
+    let rc = Rc::new(RefCell::new(1usize));
+    let mut boxed = Box::new(1usize);
+    let mut value = 1usize;
+
+    let mut group = c.benchmark_group("deref_mut");
+    group.bench_function("shared", |b| b.iter(|| **black_box(&mut shared) += 1));
+    group.bench_function("shared_borrow_mut", |b| b.iter(|| *black_box(&shared).borrow_mut() += 1));
+    group.bench_function("packed", |b| b.iter(|| **black_box(&mut packed) += 1));
+    group.bench_function("rc_refcell", |b| b.iter(|| *black_box(&rc).borrow_mut() += 1));
+    group.bench_function("box", |b| b.iter(|| **black_box(&mut boxed) += 1));
+    group.bench_function("value", |b| b.iter(|| *black_box(&mut value) += 1));
+    group.finish();
+}
+
+fn drop(c: &mut Criterion) {
+    let mut group = c.benchmark_group("drop");
+    group.bench_function("shared", |b| {
+        b.iter_batched(|| Shared::new(1usize), std::mem::drop, BatchSize::SmallInput)
+    });
+    group.bench_function("packed", |b| {
+        b.iter_batched(|| PackedShared::new_in(1usize), std::mem::drop, BatchSize::SmallInput)
+    });
+    group.bench_function("rc_refcell", |b| {
+        b.iter_batched(|| Rc::new(RefCell::new(1usize)), std::mem::drop, BatchSize::SmallInput)
+    });
+    group.bench_function("box", |b| {
+        b.iter_batched(|| Box::new(1usize), std::mem::drop, BatchSize::SmallInput)
+    });
+    group.bench_function("value", |b| {
+        b.iter_batched(|| 1usize, std::mem::drop, BatchSize::SmallInput)
+    });
+    group.finish();
+}
+
+// The `#[bench]` functions of the crate before the criterion harness.
+#[allow(clippy::useless_conversion, clippy::map_identity)]
+fn vec(c: &mut Criterion) {
+    let mut group = c.benchmark_group("vec");
+    group.bench_function("compare_shared_vec", |b| {
+        b.iter(|| {
+            let _array: Vec<_> = (0..1000).into_iter()
+                .map(|i| shared!(i))
+                .collect();
+            let _clone = _array.clone();
+        });
+    });
+    group.bench_function("compare_data_vec", |b| {
+        b.iter(|| {
+            let _array: Vec<_> = (0..1000).into_iter()
+                .map(|i| i)
+                .collect();
+            let _clone = _array.clone();
+        });
+    });
+    group.finish();
+}
+
+criterion_group!(benches, new, clone, deref, deref_mut, drop, vec);
+criterion_main!(benches);
@@ -0,0 +1,109 @@
+//! Derive macros for `shared`, enabled by its `derive` feature.
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, parse_quote, Data, DeriveInput, Error, Fields, Ident};
+
+/// Implements `shared::gc::Trace` by tracing every field, except the ones
+/// marked `#[trace(skip)]`. Type parameters are required to be `Trace`.
+#[proc_macro_derive(Trace, attributes(trace))]
+pub fn derive_trace(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match trace(input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn trace(mut input: DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let body = match &input.data {
+        Data::Struct(data) => {
+            let (pattern, fields) = destructure(&data.fields)?;
+            quote! {
+                let #name #pattern = self;
+                #(::shared::gc::Trace::trace(#fields, tracer);)*
+            }
+        }
+        Data::Enum(data) if data.variants.is_empty() => quote!(match *self {}),
+        Data::Enum(data) => {
+            let arms = data
+                .variants
+                .iter()
+                .map(|variant| {
+                    let variant_name = &variant.ident;
+                    let (pattern, fields) = destructure(&variant.fields)?;
+                    Ok(quote! {
+                        #name::#variant_name #pattern => {
+                            #(::shared::gc::Trace::trace(#fields, tracer);)*
+                        }
+                    })
+                })
+                .collect::<syn::Result<Vec<_>>>()?;
+            quote! {
+                match self {
+                    #(#arms)*
+                }
+            }
+        }
+        Data::Union(_) => {
+            return Err(Error::new(Span::call_site(), "`Trace` cannot be derived for unions"));
+        }
+    };
+
+    for param in input.generics.type_params_mut() {
+        param.bounds.push(parse_quote!(::shared::gc::Trace));
+    }
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        unsafe impl #impl_generics ::shared::gc::Trace for #name #ty_generics #where_clause {
+            #[allow(unused_variables)]
+            fn trace(&self, tracer: &mut ::shared::gc::Tracer<'_>) {
+                #body
+            }
+        }
+    })
+}
+
+/// Pattern binding the traced fields of a struct or variant, and their names.
+fn destructure(fields: &Fields) -> syn::Result<(TokenStream2, Vec<Ident>)> {
+    let mut traced = Vec::new();
+    let mut bindings = Vec::new();
+    for (i, field) in fields.iter().enumerate() {
+        let binding = format_ident!("__field{}", i);
+        let member = match &field.ident {
+            Some(ident) => quote!(#ident),
+            None => {
+                let index = syn::Index::from(i);
+                quote!(#index)
+            }
+        };
+        if skipped(field)? {
+            bindings.push(quote!(#member: _));
+        } else {
+            bindings.push(quote!(#member: #binding));
+            traced.push(binding);
+        }
+    }
+    let pattern = match fields {
+        Fields::Unit => quote!(),
+        _ => quote!({ #(#bindings,)* .. }),
+    };
+    Ok((pattern, traced))
+}
+
+fn skipped(field: &syn::Field) -> syn::Result<bool> {
+    let mut skip = false;
+    for attr in field.attrs.iter().filter(|attr| attr.path().is_ident("trace")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("skip") {
+                skip = true;
+                Ok(())
+            } else {
+                Err(meta.error("expected `skip`"))
+            }
+        })?;
+    }
+    Ok(skip)
+}
@@ -0,0 +1,63 @@
+use shared::gc::{collect_cycles, GcShared};
+use shared::{Shared, Trace};
+use std::cell::Cell;
+use std::rc::Rc;
+
+#[derive(Trace)]
+struct Node {
+    value: Pair<i32>,
+    next: Option<GcShared<Node>>,
+    #[trace(skip)]
+    drops: Rc<Cell<usize>>,
+}
+
+impl Drop for Node {
+    fn drop(&mut self) {
+        self.drops.set(self.drops.get() + 1);
+    }
+}
+
+#[derive(Trace)]
+struct Pair<T>(T, T);
+
+#[derive(Trace)]
+enum Tree {
+    Leaf,
+    Branch(GcShared<Tree>, Box<Tree>),
+    Named { children: Vec<GcShared<Tree>> },
+}
+
+#[derive(Trace)]
+struct Unit;
+
+#[derive(Trace)]
+enum Never {}
+
+#[test]
+fn struct_cycle() {
+    let drops = Rc::new(Cell::new(0));
+    let a = GcShared::new_in(Node { value: Pair(1, 2), next: None, drops: drops.clone() });
+    let b = GcShared::new_in(Node { value: Pair(3, 4), next: Some(a.clone()), drops: drops.clone() });
+    a.borrow_mut().next = Some(b.clone());
+
+    assert_eq!(a.value.0 + b.value.1, 5);
+    drop((a, b));
+    assert_eq!(collect_cycles(), 2);
+    assert_eq!(drops.get(), 2);
+}
+
+#[test]
+fn enum_cycle() {
+    let root = GcShared::new_in(Tree::Leaf);
+    let branch = GcShared::new_in(Tree::Branch(root.clone(), Box::new(Tree::Named { children: vec![root.clone()] })));
+    *root.borrow_mut() = Tree::Named { children: vec![branch.clone()] };
+
+    drop(branch);
+    assert_eq!(collect_cycles(), 0);
+    assert_eq!(root.use_count(), 3);
+    assert!(matches!(&*root, Tree::Named { children } if children[0].use_count() == 1));
+
+    drop(root);
+    assert_eq!(collect_cycles(), 2);
+    let _ = Shared::<_, shared::gc::Gc>::new_in((Unit, Option::<Never>::None));
+}
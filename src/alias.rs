@@ -0,0 +1,127 @@
+//! Debug-build bookkeeping of live borrows, used to explain aliasing panics.
+//!
+//! Every `SharedRef`/`SharedRefMut` records where it was taken, keyed by the
+//! address of the allocation. When an access conflicts with one of them, the
+//! panic message lists each live borrow together with its captured backtrace
+//! (set `RUST_BACKTRACE=1` to get full traces, the caller location is always
+//! recorded).
+//!
+//! Nothing tells when a reference returned by `deref_mut` dies, so two
+//! handles each holding a `&mut T` cannot be caught when the second one is
+//! taken. Instead the last `deref_mut` of each handle is remembered (for the
+//! most recently dereferenced values) and listed with the live borrows on
+//! the next conflicting access to the value.
+
+use std::backtrace::{Backtrace, BacktraceStatus};
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write;
+use std::panic::Location;
+
+struct Access {
+    id: u64,
+    exclusive: bool,
+    location: &'static Location<'static>,
+    backtrace: Backtrace,
+}
+
+struct DerefMut {
+    addr: usize,
+    handle: usize,
+    location: &'static Location<'static>,
+    backtrace: Backtrace,
+}
+
+// Bounds the memory kept for values that are never borrowed again.
+const DEREF_MUTS: usize = 64;
+
+thread_local! {
+    static NEXT_ID: Cell<u64> = const { Cell::new(0) };
+    static LIVE: RefCell<HashMap<usize, Vec<Access>>> = RefCell::new(HashMap::new());
+    static DEREF_MUT: RefCell<VecDeque<DerefMut>> = const { RefCell::new(VecDeque::new()) };
+}
+
+/// Registration of a live borrow, removed from the table on drop.
+pub(crate) struct AccessToken {
+    addr: usize,
+    id: u64,
+}
+
+pub(crate) fn enter(addr: usize, exclusive: bool, location: &'static Location<'static>) -> AccessToken {
+    let id = NEXT_ID.with(|next| {
+        let id = next.get();
+        next.set(id + 1);
+        id
+    });
+    let access = Access { id, exclusive, location, backtrace: Backtrace::capture() };
+    LIVE.with(|live| live.borrow_mut().entry(addr).or_default().push(access));
+    AccessToken { addr, id }
+}
+
+impl Drop for AccessToken {
+    fn drop(&mut self) {
+        // The table may already be gone if the guard outlives thread-local
+        // destruction; there is nothing left to report then.
+        let _ = LIVE.try_with(|live| {
+            let mut live = live.borrow_mut();
+            if let Some(accesses) = live.get_mut(&self.addr) {
+                accesses.retain(|access| access.id != self.id);
+                if accesses.is_empty() {
+                    live.remove(&self.addr);
+                }
+            }
+        });
+    }
+}
+
+/// Records a `deref_mut` of the allocation at `addr` through the handle at
+/// `handle`, replacing the previous one of that handle.
+pub(crate) fn deref_mut(addr: usize, handle: usize, location: &'static Location<'static>) {
+    let _ = DEREF_MUT.try_with(|sites| {
+        let mut sites = sites.borrow_mut();
+        sites.retain(|site| (site.addr, site.handle) != (addr, handle));
+        if sites.len() == DEREF_MUTS {
+            sites.pop_front();
+        }
+        sites.push_back(DerefMut { addr, handle, location, backtrace: Backtrace::capture() });
+    });
+}
+
+/// Describes the borrows of the allocation at `addr` that are still alive,
+/// and the `deref_mut` calls whose references may be.
+pub(crate) fn report(addr: usize) -> String {
+    let mut out = String::new();
+    let mut captured = false;
+    LIVE.with(|live| {
+        for access in live.borrow().get(&addr).into_iter().flatten() {
+            let kind = if access.exclusive { "mutable" } else { "shared" };
+            let _ = write!(out, "\nconflicting {} borrow taken at {}", kind, access.location);
+            if access.backtrace.status() == BacktraceStatus::Captured {
+                let _ = write!(out, "\n{}", access.backtrace);
+                captured = true;
+            }
+        }
+    });
+    let borrows = !out.is_empty();
+    if !borrows {
+        out.push_str("\nno live `SharedRef`/`SharedRefMut` recorded for this value");
+    }
+    let mut deref_muts = false;
+    DEREF_MUT.with(|sites| {
+        for site in sites.borrow().iter().filter(|site| site.addr == addr) {
+            let _ = write!(out, "\nlast `deref_mut` of the handle at {:#x}, at {}", site.handle, site.location);
+            if site.backtrace.status() == BacktraceStatus::Captured {
+                let _ = write!(out, "\n{}", site.backtrace);
+                captured = true;
+            }
+            deref_muts = true;
+        }
+    });
+    if deref_muts {
+        out.push_str("\nnote: references returned by `deref_mut` may still be alive");
+    }
+    if (borrows || deref_muts) && !captured {
+        out.push_str("\nnote: run with `RUST_BACKTRACE=1` to capture where each access started");
+    }
+    out
+}
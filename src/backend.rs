@@ -0,0 +1,491 @@
+//! Storage strategies for `Shared`.
+//!
+//! A backend is an uninhabited marker type naming how the value is allocated
+//! and guarded. [`RcRefCell`] is the default; [`RcCell`] stores `Copy` data
+//! without a borrow flag and [`ArcMutex`](crate::sync::ArcMutex) /
+//! [`ArcRwLock`](crate::sync::ArcRwLock) make the handle thread-safe.
+//! [`Packed`](crate::packed::Packed) and [`PackedCell`](crate::packed::PackedCell)
+//! drop the weak count from the allocation header, and
+//! [`Gc`](crate::gc::Gc) reclaims reference cycles.
+//! Implement [`Backend`] for your own marker to plug in another cell, and
+//! [`RawBackend`], [`WeakBackend`] or [`PointerBackend`] to enable `Deref`,
+//! weak handles or raw pointers for it.
+//!
+//! `Shared::new` always uses the default backend, other backends are built
+//! through `Shared::new_in` or `Default`.
+
+use core::cell::{Cell, Ref, RefCell, RefMut};
+use core::ops::{Deref, DerefMut};
+use alloc::rc::{Rc, Weak};
+
+pub trait Backend<T: ?Sized> {
+    /// Owning pointer to the allocation, cloning it creates another handle.
+    type Data: Clone;
+    type Ref<'a>: Deref<Target = T> where Self: 'a, T: 'a;
+    type RefMut<'a>: DerefMut<Target = T> where Self: 'a, T: 'a;
+
+    fn new(value: T) -> Self::Data where T: Sized;
+    fn strong_count(data: &Self::Data) -> usize;
+    /// Address of the allocation, used for identity comparisons.
+    fn addr(data: &Self::Data) -> *const u8;
+
+    /// Borrows the value, waiting for it if the backend can block.
+    /// `None` means the borrow conflicts with an existing one.
+    fn borrow(data: &Self::Data) -> Option<Self::Ref<'_>> {
+        Self::try_borrow(data)
+    }
+
+    /// Mutably borrows the value, see [`Backend::borrow`].
+    fn borrow_mut(data: &Self::Data) -> Option<Self::RefMut<'_>> {
+        Self::try_borrow_mut(data)
+    }
+
+    fn try_borrow(data: &Self::Data) -> Option<Self::Ref<'_>>;
+    fn try_borrow_mut(data: &Self::Data) -> Option<Self::RefMut<'_>>;
+
+    /// Returns the value if `data` is the only strong handle.
+    fn try_unwrap(data: Self::Data) -> Result<T, Self::Data> where T: Sized;
+
+    /// Returns the value if `data` is the only strong handle. Unlike
+    /// `try_unwrap`, exactly one of several concurrent callers succeeds.
+    fn into_inner(data: Self::Data) -> Option<T> where T: Sized {
+        Self::try_unwrap(data).ok()
+    }
+
+    /// Mutable access if `data` is the only handle, weak ones included.
+    fn get_mut(data: &mut Self::Data) -> Option<&mut T>;
+
+    // `WeakBackend::try_new_cyclic` for the serde support, which cannot
+    // require `WeakBackend`: `f` gets a clone of the weak handle boxed as
+    // `dyn Any`, or `None` if the backend cannot hand one out yet.
+    #[cfg(feature = "serde")]
+    #[doc(hidden)]
+    fn try_new_cyclic_any<E>(
+        f: impl FnOnce(Option<alloc::boxed::Box<dyn core::any::Any>>) -> Result<T, E>,
+    ) -> Result<Self::Data, E>
+    where
+        T: Sized + 'static,
+    {
+        f(None).map(Self::new)
+    }
+}
+
+/// Backends whose value can be reached without a guard, which is what
+/// `Deref`/`DerefMut` on `Shared` are built on.
+///
+/// # Safety
+///
+/// `as_ptr` must return a pointer to the value that stays valid for as long
+/// as any handle to the allocation is alive.
+pub unsafe trait RawBackend<T: ?Sized>: Backend<T> {
+    fn as_ptr(data: &Self::Data) -> *mut T;
+
+    /// Called before `Deref` hands out `&T`, returning `false` panics.
+    fn on_deref(data: &Self::Data) -> bool {
+        let _ = data;
+        true
+    }
+
+    /// Called before `DerefMut` hands out `&mut T`, returning `false` panics.
+    fn on_deref_mut(data: &Self::Data) -> bool {
+        let _ = data;
+        true
+    }
+}
+
+/// Backends supporting non-owning handles, see `WeakShared`.
+pub trait WeakBackend<T: ?Sized>: Backend<T> {
+    type Weak: Clone;
+
+    fn dangling() -> Self::Weak where T: Sized;
+    fn downgrade(data: &Self::Data) -> Self::Weak;
+    fn upgrade(weak: &Self::Weak) -> Option<Self::Data>;
+    fn weak_count(data: &Self::Data) -> usize;
+    fn weak_strong_count(weak: &Self::Weak) -> usize;
+    fn weak_weak_count(weak: &Self::Weak) -> usize;
+    fn weak_ptr_eq(a: &Self::Weak, b: &Self::Weak) -> bool;
+
+    /// Allocates the value returned by `f`, which gets a weak handle to the
+    /// allocation that does not upgrade until `new_cyclic` returns. The
+    /// default passes a dangling handle.
+    fn new_cyclic(f: impl FnOnce(&Self::Weak) -> T) -> Self::Data where T: Sized {
+        Self::new(f(&Self::dangling()))
+    }
+
+    /// `new_cyclic` for an `f` that can fail, in which case nothing is
+    /// allocated and the weak handles it was given never upgrade.
+    fn try_new_cyclic<E>(f: impl FnOnce(&Self::Weak) -> Result<T, E>) -> Result<Self::Data, E>
+    where
+        T: Sized,
+    {
+        f(&Self::dangling()).map(Self::new)
+    }
+}
+
+/// Backends whose handle converts to a raw pointer and back, which is what
+/// `Shared::into_raw` and `Shared::from_raw` are built on.
+pub trait PointerBackend<T: ?Sized>: Backend<T> {
+    /// Pointee of the raw pointer, the cell holding the value.
+    type Raw: ?Sized;
+
+    fn into_raw(data: Self::Data) -> *const Self::Raw;
+
+    /// # Safety
+    ///
+    /// `ptr` must come from `into_raw` of the same backend and `T`, and is
+    /// given up by the call.
+    unsafe fn from_raw(ptr: *const Self::Raw) -> Self::Data;
+}
+
+/// Implements the pointer half of a backend for `Rc` or `Arc`.
+macro_rules! pointer_backend {
+    ($ptr:ident, $cell:ident) => {
+        fn new(value: T) -> Self::Data where T: Sized {
+            $ptr::new($cell::new(value))
+        }
+
+        fn strong_count(data: &Self::Data) -> usize {
+            $ptr::strong_count(data)
+        }
+
+        fn addr(data: &Self::Data) -> *const u8 {
+            $ptr::as_ptr(data) as *const u8
+        }
+
+        #[cfg(feature = "serde")]
+        fn try_new_cyclic_any<E>(
+            f: impl FnOnce(Option<alloc::boxed::Box<dyn core::any::Any>>) -> Result<T, E>,
+        ) -> Result<Self::Data, E>
+        where
+            T: Sized + 'static,
+        {
+            <Self as WeakBackend<T>>::try_new_cyclic(|weak| f(Some(alloc::boxed::Box::new(weak.clone()))))
+        }
+    };
+    (raw $ptr:ident, $cell:ident) => {
+        type Raw = $cell<T>;
+
+        fn into_raw(data: Self::Data) -> *const Self::Raw {
+            $ptr::into_raw(data)
+        }
+
+        unsafe fn from_raw(ptr: *const Self::Raw) -> Self::Data {
+            $ptr::from_raw(ptr)
+        }
+    };
+    (weak $ptr:ident, $weak:ident, $cell:ident) => {
+        type Weak = $weak<$cell<T>>;
+
+        fn dangling() -> Self::Weak where T: Sized {
+            $weak::new()
+        }
+
+        fn downgrade(data: &Self::Data) -> Self::Weak {
+            $ptr::downgrade(data)
+        }
+
+        fn upgrade(weak: &Self::Weak) -> Option<Self::Data> {
+            weak.upgrade()
+        }
+
+        fn weak_count(data: &Self::Data) -> usize {
+            $ptr::weak_count(data)
+        }
+
+        fn weak_strong_count(weak: &Self::Weak) -> usize {
+            weak.strong_count()
+        }
+
+        fn weak_weak_count(weak: &Self::Weak) -> usize {
+            weak.weak_count()
+        }
+
+        fn weak_ptr_eq(a: &Self::Weak, b: &Self::Weak) -> bool {
+            $weak::ptr_eq(a, b)
+        }
+
+        fn new_cyclic(f: impl FnOnce(&Self::Weak) -> T) -> Self::Data where T: Sized {
+            $ptr::new_cyclic(|weak| $cell::new(f(weak)))
+        }
+
+        fn try_new_cyclic<E>(f: impl FnOnce(&Self::Weak) -> Result<T, E>) -> Result<Self::Data, E>
+        where
+            T: Sized,
+        {
+            use core::mem::MaybeUninit;
+
+            // The allocation is left uninitialized if `f` fails, strong
+            // handles to it only exist once it holds a value.
+            let mut result = Ok(());
+            let data = $ptr::<MaybeUninit<$cell<T>>>::new_cyclic(|weak| {
+                // `MaybeUninit<C>` has the layout of `C`.
+                let weak = unsafe { $weak::from_raw($weak::into_raw(weak.clone()) as *const $cell<T>) };
+                match f(&weak) {
+                    Ok(value) => MaybeUninit::new($cell::new(value)),
+                    Err(err) => {
+                        result = Err(err);
+                        MaybeUninit::uninit()
+                    }
+                }
+            });
+            result.map(|()| unsafe { data.assume_init() })
+        }
+    };
+}
+
+#[cfg(feature = "std")]
+pub(crate) use pointer_backend;
+
+/// `Rc<RefCell<T>>`, the default backend.
+pub enum RcRefCell {}
+
+impl<T: ?Sized> Backend<T> for RcRefCell {
+    type Data = Rc<RefCell<T>>;
+    type Ref<'a> = Ref<'a, T> where T: 'a;
+    type RefMut<'a> = RefMut<'a, T> where T: 'a;
+
+    pointer_backend!(Rc, RefCell);
+
+    fn try_borrow(data: &Self::Data) -> Option<Self::Ref<'_>> {
+        data.try_borrow().ok()
+    }
+
+    fn try_borrow_mut(data: &Self::Data) -> Option<Self::RefMut<'_>> {
+        data.try_borrow_mut().ok()
+    }
+
+    fn try_unwrap(data: Self::Data) -> Result<T, Self::Data> where T: Sized {
+        Rc::try_unwrap(data).map(RefCell::into_inner)
+    }
+
+    fn get_mut(data: &mut Self::Data) -> Option<&mut T> {
+        Rc::get_mut(data).map(RefCell::get_mut)
+    }
+}
+
+// Release builds hand out the value without looking at the borrow flag. With
+// the `checked` feature or debug assertions the flag is inspected (not held):
+// a live `SharedRef`/`SharedRefMut` on any handle is detected, an outstanding
+// `&mut T` from a previous `deref_mut` is not.
+unsafe impl<T: ?Sized> RawBackend<T> for RcRefCell {
+    fn as_ptr(data: &Self::Data) -> *mut T {
+        data.as_ptr()
+    }
+
+    #[cfg(feature = "checked")]
+    fn on_deref(data: &Self::Data) -> bool {
+        unsafe { data.try_borrow_unguarded() }.is_ok()
+    }
+
+    #[cfg(feature = "checked")]
+    fn on_deref_mut(data: &Self::Data) -> bool {
+        data.try_borrow_mut().is_ok()
+    }
+}
+
+impl<T: ?Sized> WeakBackend<T> for RcRefCell {
+    pointer_backend!(weak Rc, Weak, RefCell);
+}
+
+impl<T: ?Sized> PointerBackend<T> for RcRefCell {
+    pointer_backend!(raw Rc, RefCell);
+}
+
+/// `Rc<Cell<T>>` for `Copy` data, without a borrow flag.
+///
+/// Borrows never conflict: a [`CellRef`] holds a copy of the value and a
+/// [`CellRefMut`] writes its copy back when dropped.
+pub enum RcCell {}
+
+/// Copy of the value taken by [`RcCell`] borrows.
+pub struct CellRef<T> {
+    pub(crate) value: T,
+}
+
+/// Copy of the value written back to the cell on drop.
+pub struct CellRefMut<'a, T: Copy> {
+    pub(crate) cell: &'a Cell<T>,
+    pub(crate) value: T,
+}
+
+impl<T> Deref for CellRef<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T: Copy> Deref for CellRefMut<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T: Copy> DerefMut for CellRefMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
+
+impl<T: Copy> Drop for CellRefMut<'_, T> {
+    fn drop(&mut self) {
+        self.cell.set(self.value);
+    }
+}
+
+impl<T: Copy> Backend<T> for RcCell {
+    type Data = Rc<Cell<T>>;
+    type Ref<'a> = CellRef<T> where T: 'a;
+    type RefMut<'a> = CellRefMut<'a, T> where T: 'a;
+
+    pointer_backend!(Rc, Cell);
+
+    fn try_borrow(data: &Self::Data) -> Option<Self::Ref<'_>> {
+        Some(CellRef { value: data.get() })
+    }
+
+    fn try_borrow_mut(data: &Self::Data) -> Option<Self::RefMut<'_>> {
+        Some(CellRefMut { cell: data, value: data.get() })
+    }
+
+    fn try_unwrap(data: Self::Data) -> Result<T, Self::Data> {
+        Rc::try_unwrap(data).map(Cell::into_inner)
+    }
+
+    fn get_mut(data: &mut Self::Data) -> Option<&mut T> {
+        Rc::get_mut(data).map(Cell::get_mut)
+    }
+}
+
+unsafe impl<T: Copy> RawBackend<T> for RcCell {
+    fn as_ptr(data: &Self::Data) -> *mut T {
+        data.as_ptr()
+    }
+}
+
+impl<T: Copy> WeakBackend<T> for RcCell {
+    pointer_backend!(weak Rc, Weak, Cell);
+}
+
+impl<T: Copy> PointerBackend<T> for RcCell {
+    pointer_backend!(raw Rc, Cell);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::{Backend, RcCell, RcRefCell, WeakBackend};
+    #[cfg(feature = "std")]
+    use crate::sync::{ArcMutex, ArcRwLock};
+    use crate::Shared;
+    use std::cell::{Cell, RefCell, RefMut, Ref};
+    use std::rc::{Rc, Weak};
+
+    fn bump<B: Backend<i32>>(shared: &Shared<i32, B>) -> i32 {
+        *shared.borrow_mut() += 1;
+        let value = *shared.borrow();
+        value
+    }
+
+    fn check<B: Backend<i32>>() {
+        let a = Shared::<i32, B>::new_in(1);
+        let b = a.clone();
+
+        assert_eq!(bump(&a), 2);
+        assert_eq!(bump(&b), 3);
+        assert_eq!(a.use_count(), 2);
+        assert!(a == b);
+        assert!(a != Shared::default());
+    }
+
+    #[test]
+    fn generic() {
+        check::<RcRefCell>();
+        check::<RcCell>();
+        #[cfg(feature = "std")]
+        check::<ArcMutex>();
+        #[cfg(feature = "std")]
+        check::<ArcRwLock>();
+        check::<Logged>();
+        assert_eq!(BORROWS.with(Cell::get), 2);
+    }
+
+    #[test]
+    fn cell_deref() {
+        let mut a = Shared::<_, RcCell>::new_in((1, 2));
+        let b = a.clone();
+
+        a.0 += 1;
+        assert_eq!(*b, (2, 2));
+        {
+            let mut guard = b.borrow_mut();
+            guard.1 = 0;
+            assert_eq!(*a, (2, 2));
+        }
+        assert_eq!(*a, (2, 0));
+    }
+
+    #[test]
+    fn try_new_cyclic() {
+        let mut kept = None;
+        let err = RcRefCell::try_new_cyclic(|weak: &Weak<RefCell<String>>| {
+            assert!(weak.upgrade().is_none());
+            kept = Some(weak.clone());
+            Err("failed")
+        });
+        assert_eq!(err.err(), Some("failed"));
+        assert!(kept.take().unwrap().upgrade().is_none());
+
+        let data = RcRefCell::try_new_cyclic(|weak| {
+            kept = Some(weak.clone());
+            Ok::<_, ()>(String::from("a"))
+        });
+        let data = data.unwrap();
+        assert!(Rc::ptr_eq(&kept.unwrap().upgrade().unwrap(), &data));
+        assert_eq!(*data.borrow(), "a");
+    }
+
+    thread_local! {
+        static BORROWS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    /// User-supplied backend counting mutable borrows.
+    enum Logged {}
+
+    impl<T> Backend<T> for Logged {
+        type Data = Rc<RefCell<T>>;
+        type Ref<'a> = Ref<'a, T> where T: 'a;
+        type RefMut<'a> = RefMut<'a, T> where T: 'a;
+
+        fn new(value: T) -> Self::Data {
+            Rc::new(RefCell::new(value))
+        }
+
+        fn strong_count(data: &Self::Data) -> usize {
+            Rc::strong_count(data)
+        }
+
+        fn addr(data: &Self::Data) -> *const u8 {
+            Rc::as_ptr(data) as *const u8
+        }
+
+        fn try_borrow(data: &Self::Data) -> Option<Self::Ref<'_>> {
+            data.try_borrow().ok()
+        }
+
+        fn try_borrow_mut(data: &Self::Data) -> Option<Self::RefMut<'_>> {
+            BORROWS.with(|borrows| borrows.set(borrows.get() + 1));
+            data.try_borrow_mut().ok()
+        }
+
+        fn try_unwrap(data: Self::Data) -> Result<T, Self::Data> {
+            Rc::try_unwrap(data).map(RefCell::into_inner)
+        }
+
+        fn get_mut(data: &mut Self::Data) -> Option<&mut T> {
+            Rc::get_mut(data).map(RefCell::get_mut)
+        }
+    }
+}
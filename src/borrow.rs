@@ -0,0 +1,121 @@
+use core::error::Error;
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+
+#[cfg(all(feature = "std", debug_assertions))]
+use crate::alias::{self, AccessToken};
+use crate::backend::{Backend, RcRefCell};
+
+/// Shared borrow of a `Shared` value, released on drop.
+pub struct SharedRef<'b, T: ?Sized + 'b, B: Backend<T> + 'b = RcRefCell> {
+    inner: B::Ref<'b>,
+    #[cfg(all(feature = "std", debug_assertions))]
+    _access: AccessToken,
+}
+
+/// Exclusive borrow of a `Shared` value, released on drop.
+pub struct SharedRefMut<'b, T: ?Sized + 'b, B: Backend<T> + 'b = RcRefCell> {
+    inner: B::RefMut<'b>,
+    #[cfg(all(feature = "std", debug_assertions))]
+    _access: AccessToken,
+}
+
+impl<'b, T: ?Sized, B: Backend<T>> SharedRef<'b, T, B> {
+    #[track_caller]
+    pub(crate) fn new(inner: B::Ref<'b>, addr: usize) -> Self {
+        #[cfg(not(all(feature = "std", debug_assertions)))]
+        let _ = addr;
+        SharedRef {
+            inner,
+            #[cfg(all(feature = "std", debug_assertions))]
+            _access: alias::enter(addr, false, core::panic::Location::caller()),
+        }
+    }
+}
+
+impl<'b, T: ?Sized, B: Backend<T>> SharedRefMut<'b, T, B> {
+    #[track_caller]
+    pub(crate) fn new(inner: B::RefMut<'b>, addr: usize) -> Self {
+        #[cfg(not(all(feature = "std", debug_assertions)))]
+        let _ = addr;
+        SharedRefMut {
+            inner,
+            #[cfg(all(feature = "std", debug_assertions))]
+            _access: alias::enter(addr, true, core::panic::Location::caller()),
+        }
+    }
+}
+
+impl<T: ?Sized, B: Backend<T>> Deref for SharedRef<'_, T, B> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl<T: ?Sized, B: Backend<T>> Deref for SharedRefMut<'_, T, B> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl<T: ?Sized, B: Backend<T>> DerefMut for SharedRefMut<'_, T, B> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inner
+    }
+}
+
+impl<T: ?Sized + fmt::Debug, B: Backend<T>> fmt::Debug for SharedRef<'_, T, B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+impl<T: ?Sized + fmt::Debug, B: Backend<T>> fmt::Debug for SharedRefMut<'_, T, B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+/// Returned by `Shared::try_borrow` when the value is mutably borrowed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BorrowError {
+    _priv: (),
+}
+
+/// Returned by `Shared::try_borrow_mut` when the value is already borrowed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BorrowMutError {
+    _priv: (),
+}
+
+impl BorrowError {
+    pub(crate) fn new() -> Self {
+        BorrowError { _priv: () }
+    }
+}
+
+impl BorrowMutError {
+    pub(crate) fn new() -> Self {
+        BorrowMutError { _priv: () }
+    }
+}
+
+impl fmt::Display for BorrowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("`Shared` value is already mutably borrowed")
+    }
+}
+
+impl fmt::Display for BorrowMutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("`Shared` value is already borrowed")
+    }
+}
+
+impl Error for BorrowError {}
+
+impl Error for BorrowMutError {}
@@ -0,0 +1,144 @@
+use core::cmp::Ordering;
+use core::fmt;
+use core::hash::{Hash, Hasher};
+use core::ops::{Deref, DerefMut};
+
+use crate::backend::Backend;
+use crate::{Shared, SharedRef};
+
+/// Compares, hashes and orders a `Shared` by its value instead of its
+/// allocation.
+///
+/// Both values are borrowed for the comparison (in address order, so two
+/// lock-backed handles compared from different threads cannot deadlock).
+/// Handles to the same allocation are equal without borrowing, which also
+/// makes a `NaN` equal to itself. Mutating a value through another handle
+/// while it keys a map or set breaks that collection, as with any key type.
+///
+/// ```
+/// use shared::{ByValue, Shared};
+/// use std::collections::HashSet;
+///
+/// let set: HashSet<_> = vec![Shared::new(1), Shared::new(1)]
+///     .into_iter()
+///     .map(ByValue)
+///     .collect();
+/// assert_eq!(set.len(), 1);
+/// ```
+#[derive(Clone, Copy, Default)]
+pub struct ByValue<S>(pub S);
+
+impl<S> ByValue<S> {
+    pub fn into_inner(self) -> S {
+        self.0
+    }
+}
+
+impl<S> From<S> for ByValue<S> {
+    fn from(shared: S) -> Self {
+        ByValue(shared)
+    }
+}
+
+impl<S> Deref for ByValue<S> {
+    type Target = S;
+
+    fn deref(&self) -> &S {
+        &self.0
+    }
+}
+
+impl<S> DerefMut for ByValue<S> {
+    fn deref_mut(&mut self) -> &mut S {
+        &mut self.0
+    }
+}
+
+impl<S: fmt::Debug> fmt::Debug for ByValue<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ByValue").field(&self.0).finish()
+    }
+}
+
+fn borrow_both<'a, T: ?Sized, B: Backend<T>>(
+    a: &'a Shared<T, B>,
+    b: &'a Shared<T, B>,
+) -> (SharedRef<'a, T, B>, SharedRef<'a, T, B>) {
+    if Shared::addr(a) <= Shared::addr(b) {
+        let a = a.borrow();
+        (a, b.borrow())
+    } else {
+        let b = b.borrow();
+        (a.borrow(), b)
+    }
+}
+
+impl<T: ?Sized + PartialEq, B: Backend<T>> PartialEq for ByValue<Shared<T, B>> {
+    fn eq(&self, other: &Self) -> bool {
+        if Shared::ptr_eq(&self.0, &other.0) {
+            return true;
+        }
+        let (a, b) = borrow_both(&self.0, &other.0);
+        *a == *b
+    }
+}
+
+impl<T: ?Sized + Eq, B: Backend<T>> Eq for ByValue<Shared<T, B>> { }
+
+impl<T: ?Sized + PartialOrd, B: Backend<T>> PartialOrd for ByValue<Shared<T, B>> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        if Shared::ptr_eq(&self.0, &other.0) {
+            return Some(Ordering::Equal);
+        }
+        let (a, b) = borrow_both(&self.0, &other.0);
+        (*a).partial_cmp(&*b)
+    }
+}
+
+impl<T: ?Sized + Ord, B: Backend<T>> Ord for ByValue<Shared<T, B>> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        if Shared::ptr_eq(&self.0, &other.0) {
+            return Ordering::Equal;
+        }
+        let (a, b) = borrow_both(&self.0, &other.0);
+        (*a).cmp(&*b)
+    }
+}
+
+impl<T: ?Sized + Hash, B: Backend<T>> Hash for ByValue<Shared<T, B>> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.borrow().hash(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "std")]
+    use crate::sync::ArcMutex;
+    use crate::{ByValue, Shared};
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn by_value() {
+        let a = ByValue(Shared::new(String::from("a")));
+        let b = ByValue(Shared::new(String::from("b")));
+
+        assert_ne!(a, b);
+        assert_eq!(a, ByValue(Shared::new(String::from("a"))));
+        assert!(a < b);
+
+        b.borrow_mut().clear();
+        let set: BTreeSet<_> = vec![a.clone(), b.clone(), a].into_iter().collect();
+        assert_eq!(set.iter().next(), Some(&b));
+        assert_eq!(set.len(), 2);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn same_lock() {
+        let a = ByValue(Shared::<f64, ArcMutex>::new_in(f64::NAN));
+
+        assert_eq!(a, a.clone());
+        assert_ne!(a, ByValue(Shared::new_in(f64::NAN)));
+    }
+}
@@ -0,0 +1,424 @@
+//! Registry of live `Shared` allocations, enabled by the `tracking` feature.
+//!
+//! [`Tracked`] wraps another backend and stores a token next to the value,
+//! in the same allocation. The allocation is registered when it is created
+//! and forgotten when its value is dropped, on whichever thread that
+//! happens. Whatever is still listed at the end of a test has outlived it:
+//!
+//! ```
+//! use shared::debug::{dump_leaks, TrackedShared};
+//!
+//! let value = TrackedShared::<_>::new_in(1);
+//! drop(value);
+//! assert_eq!(dump_leaks(), 0);
+//! ```
+//!
+//! Handle counts are read from the allocation itself, so they include
+//! handles created through `into_raw` or `increment_strong_count`. Creation
+//! backtraces are captured as by `Backtrace::capture`, set
+//! `RUST_BACKTRACE=1` to get them.
+//!
+//! Like [`Versioned`](crate::version::Versioned), the adaptor is opt-in: the
+//! default backend stays a plain `Rc<RefCell<T>>`.
+
+use std::any::type_name;
+use std::backtrace::Backtrace;
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::fmt;
+use std::marker::PhantomData;
+use std::mem::{self, ManuallyDrop};
+use std::ops::{Deref, DerefMut};
+use std::ptr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, ThreadId};
+
+use crate::backend::{Backend, PointerBackend, RawBackend, WeakBackend};
+use crate::{RcRefCell, Shared};
+
+/// Backend adaptor registering its allocations, e.g. `Tracked<ArcMutex>`.
+pub enum Tracked<B = RcRefCell> {
+    #[doc(hidden)]
+    __Never(Infallible, PhantomData<B>),
+}
+
+/// `Shared` listed by [`live_allocations`] while its value is alive.
+pub type TrackedShared<T, B = RcRefCell> = Shared<T, Tracked<B>>;
+
+struct Entry {
+    addr: usize,
+    type_name: &'static str,
+    size: usize,
+    thread: ThreadId,
+    backtrace: Arc<Backtrace>,
+    use_count: Counter,
+}
+
+// Reads the strong count through a copy of the first handle, which is never
+// dropped. It is only called on the thread that created the allocation,
+// since the handle may not be `Send`, and while the entry exists, that is
+// while the allocation is alive.
+struct Counter(Box<dyn Fn() -> usize>);
+
+unsafe impl Send for Counter { }
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+static LIVE: Mutex<BTreeMap<u64, Entry>> = Mutex::new(BTreeMap::new());
+
+fn live() -> MutexGuard<'static, BTreeMap<u64, Entry>> {
+    LIVE.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// Unregisters the allocation when the value is dropped.
+struct Token(u64);
+
+impl Token {
+    fn next() -> Self {
+        Token(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+impl Drop for Token {
+    fn drop(&mut self) {
+        live().remove(&self.0);
+    }
+}
+
+fn register<T: 'static, B: Backend<TrackedCell<T>> + 'static>(id: u64, data: &B::Data) {
+    let handle = ManuallyDrop::new(unsafe { ptr::read(data) });
+    let entry = Entry {
+        addr: B::addr(data) as usize,
+        type_name: type_name::<T>(),
+        size: mem::size_of::<T>(),
+        thread: thread::current().id(),
+        backtrace: Arc::new(Backtrace::capture()),
+        use_count: Counter(Box::new(move || B::strong_count(&handle))),
+    };
+    live().insert(id, entry);
+}
+
+/// Value stored by the inner backend of [`Tracked`].
+pub struct TrackedCell<T: ?Sized> {
+    _token: Token,
+    value: T,
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for TrackedCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TrackedCell").field("value", &&self.value).finish()
+    }
+}
+
+/// Guard of the inner backend, dereferencing to the value.
+pub struct TrackedRef<G>(G);
+
+impl<T: ?Sized, G: Deref<Target = TrackedCell<T>>> Deref for TrackedRef<G> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0.value
+    }
+}
+
+impl<T: ?Sized, G: DerefMut<Target = TrackedCell<T>>> DerefMut for TrackedRef<G> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0.value
+    }
+}
+
+impl<T: ?Sized + 'static, B: Backend<TrackedCell<T>> + 'static> Backend<T> for Tracked<B> {
+    type Data = B::Data;
+    type Ref<'a> = TrackedRef<B::Ref<'a>> where Self: 'a, T: 'a;
+    type RefMut<'a> = TrackedRef<B::RefMut<'a>> where Self: 'a, T: 'a;
+
+    fn new(value: T) -> Self::Data where T: Sized {
+        let token = Token::next();
+        let id = token.0;
+        let data = B::new(TrackedCell { _token: token, value });
+        register::<T, B>(id, &data);
+        data
+    }
+
+    fn strong_count(data: &Self::Data) -> usize {
+        B::strong_count(data)
+    }
+
+    fn addr(data: &Self::Data) -> *const u8 {
+        B::addr(data)
+    }
+
+    fn borrow(data: &Self::Data) -> Option<Self::Ref<'_>> {
+        B::borrow(data).map(TrackedRef)
+    }
+
+    fn borrow_mut(data: &Self::Data) -> Option<Self::RefMut<'_>> {
+        B::borrow_mut(data).map(TrackedRef)
+    }
+
+    fn try_borrow(data: &Self::Data) -> Option<Self::Ref<'_>> {
+        B::try_borrow(data).map(TrackedRef)
+    }
+
+    fn try_borrow_mut(data: &Self::Data) -> Option<Self::RefMut<'_>> {
+        B::try_borrow_mut(data).map(TrackedRef)
+    }
+
+    fn try_unwrap(data: Self::Data) -> Result<T, Self::Data> where T: Sized {
+        B::try_unwrap(data).map(|cell| cell.value)
+    }
+
+    fn into_inner(data: Self::Data) -> Option<T> where T: Sized {
+        B::into_inner(data).map(|cell| cell.value)
+    }
+
+    fn get_mut(data: &mut Self::Data) -> Option<&mut T> {
+        B::get_mut(data).map(|cell| &mut cell.value)
+    }
+
+    #[cfg(feature = "serde")]
+    fn try_new_cyclic_any<E>(
+        f: impl FnOnce(Option<Box<dyn std::any::Any>>) -> Result<T, E>,
+    ) -> Result<Self::Data, E>
+    where
+        T: Sized + 'static,
+    {
+        let token = Token::next();
+        let id = token.0;
+        let data = B::try_new_cyclic_any(|weak| f(weak).map(|value| TrackedCell { _token: token, value }))?;
+        register::<T, B>(id, &data);
+        Ok(data)
+    }
+}
+
+unsafe impl<T: ?Sized + 'static, B: RawBackend<TrackedCell<T>> + 'static> RawBackend<T> for Tracked<B> {
+    fn as_ptr(data: &Self::Data) -> *mut T {
+        unsafe { ptr::addr_of_mut!((*B::as_ptr(data)).value) }
+    }
+
+    fn on_deref(data: &Self::Data) -> bool {
+        B::on_deref(data)
+    }
+
+    fn on_deref_mut(data: &Self::Data) -> bool {
+        B::on_deref_mut(data)
+    }
+}
+
+impl<T: ?Sized + 'static, B: WeakBackend<TrackedCell<T>> + 'static> WeakBackend<T> for Tracked<B> {
+    type Weak = B::Weak;
+
+    fn dangling() -> Self::Weak where T: Sized {
+        B::dangling()
+    }
+
+    fn downgrade(data: &Self::Data) -> Self::Weak {
+        B::downgrade(data)
+    }
+
+    fn upgrade(weak: &Self::Weak) -> Option<Self::Data> {
+        B::upgrade(weak)
+    }
+
+    fn weak_count(data: &Self::Data) -> usize {
+        B::weak_count(data)
+    }
+
+    fn weak_strong_count(weak: &Self::Weak) -> usize {
+        B::weak_strong_count(weak)
+    }
+
+    fn weak_weak_count(weak: &Self::Weak) -> usize {
+        B::weak_weak_count(weak)
+    }
+
+    fn weak_ptr_eq(a: &Self::Weak, b: &Self::Weak) -> bool {
+        B::weak_ptr_eq(a, b)
+    }
+
+    fn new_cyclic(f: impl FnOnce(&Self::Weak) -> T) -> Self::Data where T: Sized {
+        let token = Token::next();
+        let id = token.0;
+        let data = B::new_cyclic(|weak| TrackedCell { _token: token, value: f(weak) });
+        register::<T, B>(id, &data);
+        data
+    }
+
+    fn try_new_cyclic<E>(f: impl FnOnce(&Self::Weak) -> Result<T, E>) -> Result<Self::Data, E>
+    where
+        T: Sized,
+    {
+        let token = Token::next();
+        let id = token.0;
+        let data = B::try_new_cyclic(|weak| f(weak).map(|value| TrackedCell { _token: token, value }))?;
+        register::<T, B>(id, &data);
+        Ok(data)
+    }
+}
+
+impl<T: ?Sized + 'static, B: PointerBackend<TrackedCell<T>> + 'static> PointerBackend<T> for Tracked<B> {
+    type Raw = B::Raw;
+
+    fn into_raw(data: Self::Data) -> *const Self::Raw {
+        B::into_raw(data)
+    }
+
+    unsafe fn from_raw(ptr: *const Self::Raw) -> Self::Data {
+        B::from_raw(ptr)
+    }
+}
+
+/// Snapshot of a live allocation.
+#[derive(Clone)]
+pub struct Allocation {
+    addr: usize,
+    type_name: &'static str,
+    size: usize,
+    thread: ThreadId,
+    use_count: Option<usize>,
+    backtrace: Arc<Backtrace>,
+}
+
+impl Allocation {
+    /// Address of the allocation, as returned by `Shared::addr`.
+    pub fn addr(&self) -> usize {
+        self.addr
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Thread the allocation was created on.
+    pub fn thread(&self) -> ThreadId {
+        self.thread
+    }
+
+    /// Number of strong handles, `None` when the snapshot was taken on
+    /// another thread than [`thread`](Allocation::thread).
+    pub fn use_count(&self) -> Option<usize> {
+        self.use_count
+    }
+
+    /// Where the allocation was created.
+    pub fn backtrace(&self) -> &Backtrace {
+        &self.backtrace
+    }
+}
+
+impl fmt::Debug for Allocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Allocation")
+            .field("addr", &format_args!("{:#x}", self.addr))
+            .field("type_name", &self.type_name)
+            .field("size", &self.size)
+            .field("thread", &self.thread)
+            .field("use_count", &self.use_count)
+            .finish()
+    }
+}
+
+impl fmt::Display for Allocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({} bytes) at {:#x}, ", self.type_name, self.size, self.addr)?;
+        match self.use_count {
+            Some(count) => write!(f, "{} handle(s)", count)?,
+            None => write!(f, "created on {:?}", self.thread)?,
+        }
+        write!(f, ", created at:\n{}", self.backtrace)
+    }
+}
+
+/// Tracked allocations of every thread whose value is alive, oldest first.
+pub fn live_allocations() -> Vec<Allocation> {
+    let current = thread::current().id();
+    live()
+        .values()
+        .map(|entry| Allocation {
+            addr: entry.addr,
+            type_name: entry.type_name,
+            size: entry.size,
+            thread: entry.thread,
+            use_count: (entry.thread == current).then(|| (entry.use_count.0)()),
+            backtrace: entry.backtrace.clone(),
+        })
+        .collect()
+}
+
+/// Prints the live allocations created on this thread to stderr and returns
+/// how many there are, e.g. `assert_eq!(dump_leaks(), 0)` when a test is
+/// done. Tests running in parallel do not see each other's allocations.
+pub fn dump_leaks() -> usize {
+    let current = thread::current().id();
+    let allocations: Vec<_> =
+        live_allocations().into_iter().filter(|allocation| allocation.thread == current).collect();
+    for allocation in &allocations {
+        eprintln!("leaked {}", allocation);
+    }
+    allocations.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::debug::{dump_leaks, live_allocations, Tracked, TrackedShared};
+    use crate::sync::ArcMutex;
+    use crate::Shared;
+    use std::thread;
+
+    fn use_count(addr: usize) -> Option<usize> {
+        live_allocations().into_iter().find(|allocation| allocation.addr() == addr)?.use_count()
+    }
+
+    #[test]
+    fn live() {
+        let a = TrackedShared::<_>::new_in([0u8; 16]);
+        let b = a.clone();
+        let weak = a.downgrade();
+
+        let allocations = live_allocations();
+        let allocation = allocations.iter().find(|allocation| allocation.addr() == Shared::addr(&a)).unwrap();
+        assert_eq!(allocation.type_name(), "[u8; 16]");
+        assert_eq!(allocation.size(), 16);
+        assert_eq!(allocation.use_count(), Some(2));
+
+        drop((a, b));
+        assert_eq!(weak.upgrade(), None);
+        assert_eq!(dump_leaks(), 0);
+    }
+
+    #[test]
+    fn raw_counts() {
+        let a = TrackedShared::<_>::new_in(String::from("a"));
+        let addr = Shared::addr(&a);
+        let ptr = Shared::into_raw(a);
+        unsafe { Shared::<String, Tracked>::increment_strong_count(ptr) };
+        assert_eq!(use_count(addr), Some(2));
+
+        unsafe { Shared::<String, Tracked>::decrement_strong_count(ptr) };
+        assert_eq!(use_count(addr), Some(1));
+        let a = unsafe { Shared::<String, Tracked>::from_raw(ptr) };
+        assert_eq!(Shared::try_unwrap(a).unwrap(), "a");
+        assert_eq!(dump_leaks(), 0);
+    }
+
+    #[test]
+    fn other_threads() {
+        let a = Shared::<_, Tracked<ArcMutex>>::new_in(String::from("a"));
+        let addr = Shared::addr(&a);
+        let b = a.clone();
+        thread::spawn(move || {
+            assert_eq!(use_count(addr), None);
+            b.borrow_mut().push('b');
+        })
+        .join()
+        .unwrap();
+        assert_eq!(use_count(addr), Some(1));
+
+        thread::spawn(move || drop(a)).join().unwrap();
+        assert_eq!(dump_leaks(), 0);
+    }
+}
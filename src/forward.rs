@@ -0,0 +1,263 @@
+//! Traits of `T` forwarded to `Shared<T>`.
+//!
+//! Mutating traits go through `borrow_mut`, so they work with every backend
+//! and panic on a conflicting borrow; traits returning references into the
+//! value need a [`RawBackend`].
+
+use core::error::Error;
+use core::fmt;
+use core::hash::Hasher;
+use core::ops::{Index, IndexMut};
+
+use crate::backend::{Backend, RawBackend};
+use crate::{ByValue, Shared};
+
+// Like the `Debug` of `RefCell`, a conflicting borrow prints a placeholder
+// rather than panicking or waiting for a lock.
+impl<T: ?Sized + fmt::Display, B: Backend<T>> fmt::Display for Shared<T, B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.try_borrow() {
+            Ok(value) => value.fmt(f),
+            Err(_) => f.write_str("<borrowed>"),
+        }
+    }
+}
+
+impl<T: ?Sized, B: Backend<T>> fmt::Pointer for Shared<T, B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Pointer::fmt(&B::addr(&self.data), f)
+    }
+}
+
+// Not implemented for `Shared` itself, which compares and hashes by
+// allocation: `Borrow` requires both to agree with `T`.
+impl<T: ?Sized, B: RawBackend<T>> core::borrow::Borrow<T> for ByValue<Shared<T, B>> {
+    fn borrow(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: ?Sized, B: RawBackend<T>> core::borrow::BorrowMut<T> for ByValue<Shared<T, B>> {
+    fn borrow_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T: ?Sized + Index<I>, B: RawBackend<T>, I> Index<I> for Shared<T, B> {
+    type Output = T::Output;
+
+    #[cfg_attr(feature = "checked", track_caller)]
+    fn index(&self, index: I) -> &T::Output {
+        &(**self)[index]
+    }
+}
+
+impl<T: ?Sized + IndexMut<I>, B: RawBackend<T>, I> IndexMut<I> for Shared<T, B> {
+    #[cfg_attr(feature = "checked", track_caller)]
+    fn index_mut(&mut self, index: I) -> &mut T::Output {
+        &mut (**self)[index]
+    }
+}
+
+macro_rules! assign_ops {
+    ($($trait:ident::$method:ident),* $(,)?) => {
+        $(impl<T: ?Sized + core::ops::$trait<R>, B: Backend<T>, R> core::ops::$trait<R> for Shared<T, B> {
+            #[track_caller]
+            fn $method(&mut self, rhs: R) {
+                self.borrow_mut().$method(rhs);
+            }
+        })*
+    };
+}
+
+assign_ops! {
+    AddAssign::add_assign,
+    SubAssign::sub_assign,
+    MulAssign::mul_assign,
+    DivAssign::div_assign,
+    RemAssign::rem_assign,
+    BitAndAssign::bitand_assign,
+    BitOrAssign::bitor_assign,
+    BitXorAssign::bitxor_assign,
+    ShlAssign::shl_assign,
+    ShrAssign::shr_assign,
+}
+
+impl<I: ?Sized + Iterator, B: Backend<I>> Iterator for Shared<I, B> {
+    type Item = I::Item;
+
+    #[track_caller]
+    fn next(&mut self) -> Option<I::Item> {
+        self.borrow_mut().next()
+    }
+
+    #[track_caller]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.borrow().size_hint()
+    }
+}
+
+impl<H: ?Sized + Hasher, B: Backend<H>> Hasher for Shared<H, B> {
+    #[track_caller]
+    fn finish(&self) -> u64 {
+        self.borrow().finish()
+    }
+
+    #[track_caller]
+    fn write(&mut self, bytes: &[u8]) {
+        self.borrow_mut().write(bytes)
+    }
+}
+
+impl<T: ?Sized + Error, B: RawBackend<T>> Error for Shared<T, B>
+where
+    B::Data: fmt::Debug,
+{
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        (**self).source()
+    }
+}
+
+#[cfg(feature = "std")]
+mod io {
+    use std::io::{self, IoSlice, IoSliceMut, Read, Write};
+
+    use crate::backend::Backend;
+    use crate::Shared;
+
+    impl<R: ?Sized + Read, B: Backend<R>> Read for Shared<R, B> {
+        #[track_caller]
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.borrow_mut().read(buf)
+        }
+
+        #[track_caller]
+        fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> io::Result<usize> {
+            self.borrow_mut().read_vectored(bufs)
+        }
+    }
+
+    impl<W: ?Sized + Write, B: Backend<W>> Write for Shared<W, B> {
+        #[track_caller]
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.borrow_mut().write(buf)
+        }
+
+        #[track_caller]
+        fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+            self.borrow_mut().write_vectored(bufs)
+        }
+
+        #[track_caller]
+        fn flush(&mut self) -> io::Result<()> {
+            self.borrow_mut().flush()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "std")]
+    use crate::sync::ArcMutex;
+    use crate::{ByValue, Shared};
+    use std::borrow::{Borrow, BorrowMut};
+    use std::collections::hash_map::DefaultHasher;
+    use std::collections::HashMap;
+    use std::fmt;
+    use std::hash::Hasher;
+    #[cfg(feature = "std")]
+    use std::io::{Read, Write};
+
+    #[test]
+    fn display() {
+        let a = Shared::new(1.5);
+        assert_eq!(a.to_string(), "1.5");
+        assert_eq!(format!("{:p}", a), format!("{:#x}", Shared::addr(&a)));
+    }
+
+    #[test]
+    fn display_borrowed() {
+        let a = Shared::new(1.5);
+        let _guard = a.borrow_mut();
+        assert_eq!(a.to_string(), "<borrowed>");
+    }
+
+    #[test]
+    fn borrow() {
+        fn len<S: Borrow<str>>(s: S) -> usize {
+            s.borrow().len()
+        }
+
+        let a = Shared::from_str("abc");
+        assert_eq!(len(ByValue(a.clone())), 3);
+
+        let mut b = ByValue(Shared::new(String::from("a")));
+        BorrowMut::<String>::borrow_mut(&mut b).push('b');
+        assert_eq!(*b.0, "ab");
+
+        let map = HashMap::from([(ByValue(a), 1)]);
+        assert_eq!(map.get("abc"), Some(&1));
+    }
+
+    #[test]
+    fn ops() {
+        let mut a = Shared::new(vec![1, 2]);
+        a[0] += 10;
+        assert_eq!(a[..], [11, 2]);
+
+        #[cfg(feature = "std")]
+        {
+            let mut b = Shared::<_, ArcMutex>::new_in(6);
+            let c = b.clone();
+            b *= 7;
+            b -= 2;
+            assert_eq!(*c.borrow(), 40);
+        }
+    }
+
+    #[test]
+    fn iterator_and_hasher() {
+        let iter = Shared::new(1..4);
+        assert_eq!(iter.clone().take(2).sum::<i32>(), 3);
+        assert_eq!(iter.collect::<Vec<_>>(), [3]);
+
+        let mut hasher = Shared::new(DefaultHasher::new());
+        let mut expected = DefaultHasher::new();
+        hasher.write_u32(1);
+        expected.write_u32(1);
+        assert_eq!(hasher.finish(), expected.finish());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn io() {
+        let mut out = Shared::new(Vec::new());
+        write!(out.clone(), "hello").unwrap();
+        out.flush().unwrap();
+        assert_eq!(*out, b"hello");
+
+        let mut input = Shared::new(&b"abc"[..]);
+        let mut buf = [0; 2];
+        input.clone().read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"ab");
+        assert_eq!(input.read(&mut buf).unwrap(), 1);
+    }
+
+    #[test]
+    fn error() {
+        #[derive(Debug)]
+        struct Inner;
+
+        impl fmt::Display for Inner {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("inner")
+            }
+        }
+
+        impl std::error::Error for Inner {}
+
+        let err: Box<dyn std::error::Error> = Box::new(Shared::new(Inner));
+        assert_eq!(err.to_string(), "inner");
+        assert!(err.source().is_none());
+    }
+}
@@ -0,0 +1,704 @@
+//! Cycle-collected `Shared` values.
+//!
+//! A [`GcShared`] behaves like the default `Shared`, but its value implements
+//! [`Trace`] to report the `GcShared` handles it owns. Handles whose count
+//! drops to a non-zero value are remembered as possible roots of a garbage
+//! cycle, and [`collect_cycles`] reclaims the cycles among them by trial
+//! deletion (Bacon and Rajan, "Concurrent Cycle Collection in Reference
+//! Counted Systems", synchronous variant): it subtracts the references
+//! internal to the subgraph, restores everything still reachable from a
+//! handle outside it, and frees the rest.
+//!
+//! Like `Rc`, the handles and the collector are per thread. A value that is
+//! mutably borrowed while collecting is treated as having no children, which
+//! keeps everything it points to alive until the next collection.
+//!
+//! `Drop` impls of collected values must not resurrect the cycle: cloning a
+//! handle to it panics, and a handle moved out of it no longer gives access
+//! to the (dropped) value.
+
+use std::cell::{Cell, Ref, RefCell, RefMut};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::marker::PhantomData;
+use std::mem::ManuallyDrop;
+use std::ptr::{self, NonNull};
+use std::rc::Rc;
+
+use crate::backend::{Backend, RawBackend};
+use crate::Shared;
+
+/// `Shared` whose reference cycles can be reclaimed with [`collect_cycles`].
+pub type GcShared<T> = Shared<T, Gc>;
+
+/// Reference-counted, cycle-collected `RefCell<T>`.
+pub enum Gc {}
+
+/// Reports the `GcShared` handles owned by a value.
+///
+/// Use `#[derive(Trace)]` with the `derive` feature, fields marked
+/// `#[trace(skip)]` are not traced. Types owning no `GcShared` can use the
+/// default, empty `trace`.
+///
+/// # Safety
+///
+/// `trace` must visit every handle at most once and only handles owned by
+/// `self`, not ones reached through shared ownership such as an `Rc`.
+/// Visiting too few handles only leaks, visiting others frees live values.
+pub unsafe trait Trace {
+    fn trace(&self, tracer: &mut Tracer<'_>) {
+        let _ = tracer;
+    }
+}
+
+/// Visitor passed to [`Trace::trace`].
+pub struct Tracer<'a> {
+    children: &'a mut Vec<NodePtr>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Color {
+    // In use or free.
+    Black,
+    // Possible member of a cycle.
+    Gray,
+    // Member of a garbage cycle.
+    White,
+    // Possible root of a cycle.
+    Purple,
+    // Collected, but handles escaped the drop of the cycle (moved out by a
+    // `Drop` impl). The allocation is freed with the last of them.
+    Dropped,
+}
+
+struct Header {
+    count: Cell<usize>,
+    color: Cell<Color>,
+    buffered: Cell<bool>,
+    // Set once the collector has picked the value as garbage, for good.
+    dead: Cell<bool>,
+}
+
+struct GcBox<T: ?Sized> {
+    header: Header,
+    value: ManuallyDrop<RefCell<T>>,
+}
+
+trait Node {
+    fn header(&self) -> &Header;
+    fn children(&self) -> Vec<NodePtr>;
+    /// # Safety
+    ///
+    /// Called once, when no handle can reach the value anymore.
+    unsafe fn drop_value(&self);
+}
+
+type NodePtr = NonNull<dyn Node>;
+
+impl<T: Trace> Node for GcBox<T> {
+    fn header(&self) -> &Header {
+        &self.header
+    }
+
+    fn children(&self) -> Vec<NodePtr> {
+        let mut children = Vec::new();
+        if let Ok(value) = self.value.try_borrow() {
+            value.trace(&mut Tracer { children: &mut children });
+        }
+        children
+    }
+
+    // The cell stays mutably borrowed afterwards, so escaped handles cannot
+    // borrow the dropped value. A value borrowed for good (a leaked guard)
+    // is leaked instead.
+    unsafe fn drop_value(&self) {
+        if let Ok(value) = self.value.try_borrow_mut() {
+            let mut value = ManuallyDrop::new(value);
+            ptr::drop_in_place(&mut **value as *mut T);
+        }
+    }
+}
+
+thread_local! {
+    static ROOTS: RefCell<Vec<NodePtr>> = const { RefCell::new(Vec::new()) };
+    static COLLECTING: Cell<bool> = const { Cell::new(false) };
+}
+
+fn header<'a>(node: NodePtr) -> &'a Header {
+    unsafe { (*node.as_ptr()).header() }
+}
+
+fn children(node: NodePtr) -> Vec<NodePtr> {
+    unsafe { node.as_ref().children() }
+}
+
+unsafe fn dealloc(node: NodePtr) {
+    drop(Box::from_raw(node.as_ptr()));
+}
+
+fn decrement(node: NodePtr) {
+    let header = header(node);
+    header.count.set(header.count.get() - 1);
+    if header.dead.get() {
+        // The collector owns the allocation until it marks it `Dropped`.
+        if header.count.get() == 0 && header.color.get() == Color::Dropped {
+            unsafe { dealloc(node) };
+        }
+        return;
+    }
+    if header.count.get() == 0 {
+        header.color.set(Color::Black);
+        unsafe { node.as_ref().drop_value() };
+        if !self::header(node).buffered.get() {
+            unsafe { dealloc(node) };
+        }
+    } else if header.color.get() != Color::Purple {
+        header.color.set(Color::Purple);
+        if !header.buffered.get() {
+            // Without thread-locals (at thread exit) the cycle just leaks.
+            let pushed = ROOTS.try_with(|roots| roots.borrow_mut().push(node)).is_ok();
+            header.buffered.set(pushed);
+        }
+    }
+}
+
+/// Frees the garbage cycles among the handles dropped on this thread since
+/// the last collection, returning how many values were freed.
+pub fn collect_cycles() -> usize {
+    // A panicking `Drop` leaks the rest of the garbage, but collection
+    // keeps working.
+    struct Collecting;
+
+    impl Drop for Collecting {
+        fn drop(&mut self) {
+            COLLECTING.with(|collecting| collecting.set(false));
+        }
+    }
+
+    if COLLECTING.with(|collecting| collecting.replace(true)) {
+        return 0;
+    }
+    let _collecting = Collecting;
+    let roots = ROOTS.with(|roots| roots.take());
+
+    let mut candidates = Vec::new();
+    for node in roots {
+        let header = header(node);
+        if header.color.get() == Color::Purple {
+            mark_gray(node);
+            candidates.push(node);
+        } else {
+            header.buffered.set(false);
+            if header.color.get() == Color::Black && header.count.get() == 0 {
+                unsafe { dealloc(node) };
+            }
+        }
+    }
+    for &node in &candidates {
+        scan(node);
+    }
+    let mut garbage = Vec::new();
+    for node in candidates {
+        header(node).buffered.set(false);
+        collect_white(node, &mut garbage);
+    }
+
+    // Edges from garbage were subtracted by `mark_gray`, give them back so
+    // dropping the garbage releases them normally. A garbage count still
+    // above zero afterwards is a handle that escaped the drop.
+    for &node in &garbage {
+        for child in children(node) {
+            let header = header(child);
+            if header.color.get() != Color::Dropped {
+                header.count.set(header.count.get() + 1);
+            }
+        }
+    }
+    for &node in &garbage {
+        unsafe { node.as_ref().drop_value() };
+    }
+    let freed = garbage.len();
+    for node in garbage {
+        let header = header(node);
+        if header.count.get() == 0 {
+            unsafe { dealloc(node) };
+        } else {
+            header.color.set(Color::Dropped);
+        }
+    }
+    freed
+}
+
+fn mark_gray(node: NodePtr) {
+    let mut stack = Vec::new();
+    if header(node).color.get() != Color::Gray {
+        header(node).color.set(Color::Gray);
+        stack.push(node);
+    }
+    while let Some(node) = stack.pop() {
+        for child in children(node) {
+            let header = header(child);
+            if header.dead.get() {
+                continue;
+            }
+            header.count.set(header.count.get() - 1);
+            if header.color.get() != Color::Gray {
+                header.color.set(Color::Gray);
+                stack.push(child);
+            }
+        }
+    }
+}
+
+fn scan(node: NodePtr) {
+    let mut stack = vec![node];
+    while let Some(node) = stack.pop() {
+        let header = header(node);
+        if header.color.get() != Color::Gray {
+            continue;
+        }
+        if header.count.get() > 0 {
+            scan_black(node);
+        } else {
+            header.color.set(Color::White);
+            stack.extend(children(node));
+        }
+    }
+}
+
+fn scan_black(node: NodePtr) {
+    header(node).color.set(Color::Black);
+    let mut stack = vec![node];
+    while let Some(node) = stack.pop() {
+        for child in children(node) {
+            let header = header(child);
+            if header.dead.get() {
+                continue;
+            }
+            header.count.set(header.count.get() + 1);
+            if header.color.get() != Color::Black {
+                header.color.set(Color::Black);
+                stack.push(child);
+            }
+        }
+    }
+}
+
+fn collect_white(node: NodePtr, garbage: &mut Vec<NodePtr>) {
+    let mut stack = vec![node];
+    while let Some(node) = stack.pop() {
+        let header = header(node);
+        if header.color.get() != Color::White || header.buffered.get() {
+            continue;
+        }
+        header.color.set(Color::Black);
+        header.dead.set(true);
+        garbage.push(node);
+        stack.extend(children(node));
+    }
+}
+
+/// Handle to a [`Gc`] allocation.
+pub struct GcPtr<T: Trace + 'static> {
+    ptr: NonNull<GcBox<T>>,
+    _marker: PhantomData<GcBox<T>>,
+}
+
+impl<T: Trace + 'static> GcPtr<T> {
+    fn new(value: T) -> Self {
+        let inner = Box::new(GcBox {
+            header: Header {
+                count: Cell::new(1),
+                color: Cell::new(Color::Black),
+                buffered: Cell::new(false),
+                dead: Cell::new(false),
+            },
+            value: ManuallyDrop::new(RefCell::new(value)),
+        });
+        GcPtr { ptr: NonNull::from(Box::leak(inner)), _marker: PhantomData }
+    }
+
+    fn inner(&self) -> &GcBox<T> {
+        unsafe { self.ptr.as_ref() }
+    }
+
+    fn node(&self) -> NodePtr {
+        self.ptr
+    }
+}
+
+impl<T: Trace + 'static> Clone for GcPtr<T> {
+    #[track_caller]
+    fn clone(&self) -> Self {
+        let header = &self.inner().header;
+        if header.dead.get() {
+            panic!("cannot clone a `GcShared` whose value is being collected");
+        }
+        header.count.set(header.count.get() + 1);
+        header.color.set(Color::Black);
+        GcPtr { ptr: self.ptr, _marker: PhantomData }
+    }
+}
+
+impl<T: Trace + 'static> Drop for GcPtr<T> {
+    fn drop(&mut self) {
+        decrement(self.node());
+    }
+}
+
+impl<T: Trace + fmt::Debug + 'static> fmt::Debug for GcPtr<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (*self.inner().value).fmt(f)
+    }
+}
+
+impl<T: Trace + 'static> Backend<T> for Gc {
+    type Data = GcPtr<T>;
+    type Ref<'a> = Ref<'a, T>;
+    type RefMut<'a> = RefMut<'a, T>;
+
+    fn new(value: T) -> Self::Data {
+        GcPtr::new(value)
+    }
+
+    fn strong_count(data: &Self::Data) -> usize {
+        data.inner().header.count.get()
+    }
+
+    fn addr(data: &Self::Data) -> *const u8 {
+        data.ptr.as_ptr() as *const u8
+    }
+
+    fn try_borrow(data: &Self::Data) -> Option<Self::Ref<'_>> {
+        data.inner().value.try_borrow().ok()
+    }
+
+    fn try_borrow_mut(data: &Self::Data) -> Option<Self::RefMut<'_>> {
+        data.inner().value.try_borrow_mut().ok()
+    }
+
+    fn try_unwrap(data: Self::Data) -> Result<T, Self::Data> {
+        let header = &data.inner().header;
+        if header.count.get() != 1 || header.dead.get() {
+            return Err(data);
+        }
+        let data = ManuallyDrop::new(data);
+        let value = unsafe { ptr::read(&*data.inner().value) };
+        // A buffered allocation is freed by the next collection.
+        let header = &data.inner().header;
+        header.count.set(0);
+        header.color.set(Color::Black);
+        if !header.buffered.get() {
+            unsafe { dealloc(data.node()) };
+        }
+        Ok(value.into_inner())
+    }
+
+    fn get_mut(data: &mut Self::Data) -> Option<&mut T> {
+        let header = &data.inner().header;
+        if header.count.get() != 1 || header.dead.get() {
+            return None;
+        }
+        Some(unsafe { (*data.ptr.as_ptr()).value.get_mut() })
+    }
+}
+
+// Same checks as `RcRefCell`, plus a handle that escaped the collection
+// of its value never dereferences.
+unsafe impl<T: Trace + 'static> RawBackend<T> for Gc {
+    fn as_ptr(data: &Self::Data) -> *mut T {
+        data.inner().value.as_ptr()
+    }
+
+    fn on_deref(data: &Self::Data) -> bool {
+        let inner = data.inner();
+        !inner.header.dead.get()
+            && (cfg!(not(feature = "checked"))
+                || unsafe { inner.value.try_borrow_unguarded() }.is_ok())
+    }
+
+    fn on_deref_mut(data: &Self::Data) -> bool {
+        let inner = data.inner();
+        !inner.header.dead.get()
+            && (cfg!(not(feature = "checked"))
+                || inner.value.try_borrow_mut().is_ok())
+    }
+}
+
+unsafe impl<T: Trace + 'static> Trace for Shared<T, Gc> {
+    fn trace(&self, tracer: &mut Tracer<'_>) {
+        tracer.children.push(self.data.node());
+    }
+}
+
+macro_rules! leaf {
+    ($($ty:ty),* $(,)?) => {
+        $(unsafe impl Trace for $ty {})*
+    };
+}
+
+leaf!(bool, char, str, String, u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64);
+
+// Handles behind shared ownership may be visited through another owner.
+unsafe impl<T: ?Sized> Trace for Rc<T> {}
+
+unsafe impl<T: ?Sized> Trace for PhantomData<T> {}
+
+unsafe impl<T: Copy> Trace for Cell<T> {}
+
+unsafe impl<T: ?Sized + Trace> Trace for RefCell<T> {
+    fn trace(&self, tracer: &mut Tracer<'_>) {
+        if let Ok(value) = self.try_borrow() {
+            value.trace(tracer);
+        }
+    }
+}
+
+unsafe impl<T: ?Sized + Trace> Trace for Box<T> {
+    fn trace(&self, tracer: &mut Tracer<'_>) {
+        (**self).trace(tracer);
+    }
+}
+
+unsafe impl<T: Trace> Trace for Option<T> {
+    fn trace(&self, tracer: &mut Tracer<'_>) {
+        if let Some(value) = self {
+            value.trace(tracer);
+        }
+    }
+}
+
+unsafe impl<T: Trace, E: Trace> Trace for Result<T, E> {
+    fn trace(&self, tracer: &mut Tracer<'_>) {
+        match self {
+            Ok(value) => value.trace(tracer),
+            Err(err) => err.trace(tracer),
+        }
+    }
+}
+
+unsafe impl<T: Trace> Trace for [T] {
+    fn trace(&self, tracer: &mut Tracer<'_>) {
+        for value in self {
+            value.trace(tracer);
+        }
+    }
+}
+
+unsafe impl<T: Trace, const N: usize> Trace for [T; N] {
+    fn trace(&self, tracer: &mut Tracer<'_>) {
+        self[..].trace(tracer);
+    }
+}
+
+macro_rules! collection {
+    ($($ty:ident<$($param:ident),+> => |$value:pat_param| $($field:ident)+),* $(,)?) => {
+        $(unsafe impl<$($param: Trace),+> Trace for $ty<$($param),+> {
+            fn trace(&self, tracer: &mut Tracer<'_>) {
+                for $value in self {
+                    $($field.trace(tracer);)+
+                }
+            }
+        })*
+    };
+}
+
+collection! {
+    Vec<T> => |value| value,
+    VecDeque<T> => |value| value,
+    BTreeSet<T> => |value| value,
+    HashSet<T> => |value| value,
+    BTreeMap<K, V> => |(key, value)| key value,
+    HashMap<K, V> => |(key, value)| key value,
+}
+
+macro_rules! tuple {
+    ($($name:ident)*) => {
+        unsafe impl<$($name: Trace),*> Trace for ($($name,)*) {
+            #[allow(non_snake_case, unused_variables)]
+            fn trace(&self, tracer: &mut Tracer<'_>) {
+                let ($($name,)*) = self;
+                $($name.trace(tracer);)*
+            }
+        }
+    };
+}
+
+tuple!();
+tuple!(A);
+tuple!(A B);
+tuple!(A B C);
+tuple!(A B C D);
+tuple!(A B C D E);
+tuple!(A B C D E F);
+
+#[cfg(test)]
+mod tests {
+    use crate::gc::{collect_cycles, GcShared, Trace, Tracer};
+    use crate::Shared;
+    use std::cell::{Cell, RefCell};
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    use std::rc::Rc;
+
+    struct Node {
+        edges: Vec<GcShared<Node>>,
+        drops: Rc<Cell<usize>>,
+    }
+
+    unsafe impl Trace for Node {
+        fn trace(&self, tracer: &mut Tracer<'_>) {
+            self.edges.trace(tracer);
+        }
+    }
+
+    impl Drop for Node {
+        fn drop(&mut self) {
+            self.drops.set(self.drops.get() + 1);
+        }
+    }
+
+    fn node(drops: &Rc<Cell<usize>>) -> GcShared<Node> {
+        GcShared::new_in(Node { edges: Vec::new(), drops: drops.clone() })
+    }
+
+    #[test]
+    fn acyclic() {
+        let drops = Rc::new(Cell::new(0));
+        let a = node(&drops);
+        let b = node(&drops);
+        a.borrow_mut().edges.push(b.clone());
+
+        drop(b);
+        assert_eq!(a.edges[0].use_count(), 1);
+        drop(a);
+        assert_eq!(drops.get(), 2);
+        assert_eq!(collect_cycles(), 0);
+    }
+
+    #[test]
+    fn cycle() {
+        let drops = Rc::new(Cell::new(0));
+        let a = node(&drops);
+        let b = node(&drops);
+        a.borrow_mut().edges.push(b.clone());
+        b.borrow_mut().edges.push(a.clone());
+
+        drop(b);
+        assert_eq!(collect_cycles(), 0);
+        assert_eq!(a.use_count(), 2);
+        assert_eq!(a.edges[0].use_count(), 1);
+
+        drop(a);
+        assert_eq!(drops.get(), 0);
+        assert_eq!(collect_cycles(), 2);
+        assert_eq!(drops.get(), 2);
+    }
+
+    #[test]
+    fn cycle_with_live_tail() {
+        let drops = Rc::new(Cell::new(0));
+        let tail_drops = Rc::new(Cell::new(0));
+        let a = node(&drops);
+        let b = node(&drops);
+        let tail = node(&tail_drops);
+        a.borrow_mut().edges.push(b.clone());
+        b.borrow_mut().edges.extend([a.clone(), tail.clone()]);
+        tail.borrow_mut().edges.push(tail.clone());
+
+        drop((a, b));
+        assert_eq!(tail.use_count(), 3);
+        assert_eq!(collect_cycles(), 2);
+        assert_eq!(drops.get(), 2);
+        assert_eq!(tail.use_count(), 2);
+        assert_eq!(tail.edges.len(), 1);
+
+        drop(tail);
+        assert_eq!(collect_cycles(), 1);
+        assert_eq!(tail_drops.get(), 1);
+    }
+
+    #[test]
+    fn borrowed_during_collection() {
+        let drops = Rc::new(Cell::new(0));
+        let a = node(&drops);
+        a.borrow_mut().edges.push(a.clone());
+        let b = a.clone();
+        let guard = b.borrow_mut();
+        drop(a);
+
+        assert_eq!(collect_cycles(), 0);
+        drop(guard);
+        drop(b);
+        assert_eq!(collect_cycles(), 1);
+        assert_eq!(drops.get(), 1);
+    }
+
+    #[test]
+    fn unwrap_buffered() {
+        let drops = Rc::new(Cell::new(0));
+        let a = node(&drops);
+        drop(a.clone());
+        let value = Shared::try_unwrap(a).ok().unwrap();
+        assert_eq!(collect_cycles(), 0);
+        drop(value);
+        assert_eq!(drops.get(), 1);
+    }
+
+    thread_local! {
+        static STASH: RefCell<Vec<GcShared<Zombie>>> = const { RefCell::new(Vec::new()) };
+    }
+
+    // Moves (or clones) its handle out of the cycle while being collected.
+    struct Zombie {
+        next: Option<GcShared<Zombie>>,
+        clone: bool,
+    }
+
+    unsafe impl Trace for Zombie {
+        fn trace(&self, tracer: &mut Tracer<'_>) {
+            self.next.trace(tracer);
+        }
+    }
+
+    impl Drop for Zombie {
+        fn drop(&mut self) {
+            let next = if self.clone { self.next.clone() } else { self.next.take() };
+            STASH.with(|stash| stash.borrow_mut().extend(next));
+        }
+    }
+
+    fn zombies(clone: bool) {
+        let a = GcShared::new_in(Zombie { next: None, clone });
+        let b = GcShared::new_in(Zombie { next: Some(a.clone()), clone });
+        a.borrow_mut().next = Some(b);
+    }
+
+    #[test]
+    fn escaped_handles() {
+        zombies(false);
+        assert_eq!(collect_cycles(), 2);
+
+        let stash = STASH.with(|stash| stash.take());
+        assert_eq!(stash.len(), 2);
+        for mut zombie in stash {
+            assert!(zombie.try_borrow().is_err());
+            assert!(Shared::get_mut(&mut zombie).is_none());
+            assert!(catch_unwind(AssertUnwindSafe(|| zombie.clone())).is_err());
+            assert!(catch_unwind(AssertUnwindSafe(|| zombie.next.is_none())).is_err());
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // leaks the rest of the cycle
+    fn cloned_while_collected() {
+        zombies(true);
+        assert!(catch_unwind(collect_cycles).is_err());
+        STASH.with(|stash| assert!(stash.borrow().is_empty()));
+
+        let drops = Rc::new(Cell::new(0));
+        let a = node(&drops);
+        a.borrow_mut().edges.push(a.clone());
+        drop(a);
+        assert_eq!(collect_cycles(), 1);
+    }
+}
@@ -0,0 +1,420 @@
+//! Serde support, enabled by the `serde` feature.
+//!
+//! On its own a `Shared` serializes as its value and a `WeakShared` as an
+//! `Option` of it, like `Rc` and `Weak` with serde's `rc` feature: values
+//! reachable through several handles are written several times and
+//! deserialized into separate allocations.
+//!
+//! Inside a [`SharedGraph`] every allocation is written once, together with
+//! an id, and later handles to it as references to that id. Deserializing
+//! the same graph restores the sharing. A weak handle can refer to an
+//! allocation that is still being deserialized, such as the parent of the
+//! value containing it, so cycles closed by weak handles come back too. The
+//! `Rc` and `Arc` backends support that; with other backends, such a weak
+//! handle is a deserialization error.
+//!
+//! Both encodings are different, data written inside a `SharedGraph` has to
+//! be read inside one as well.
+
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::marker::PhantomData;
+
+use serde::de::{self, Deserializer, EnumAccess, SeqAccess, VariantAccess, Visitor};
+use serde::ser::{self, SerializeTupleVariant, Serializer};
+use serde::{Deserialize, Serialize};
+
+use crate::backend::{Backend, WeakBackend};
+use crate::{Shared, WeakShared};
+
+const NAME: &str = "SharedHandle";
+const VARIANTS: &[&str] = &["Value", "Ref", "Dangling"];
+
+enum Slot {
+    // `B::Weak` of an allocation being deserialized, if the backend has one.
+    Pending(Option<Box<dyn Any>>),
+    // `Shared<T, B>`, kept alive until the end of the session.
+    Done(Box<dyn Any>),
+}
+
+thread_local! {
+    static WRITTEN: RefCell<Option<HashMap<usize, u64>>> = const { RefCell::new(None) };
+    static READ: RefCell<Option<HashMap<u64, Slot>>> = const { RefCell::new(None) };
+}
+
+/// Wrapper (de)serializing its content as a graph, where each `Shared`
+/// allocation appears once. Nested graphs are part of the outermost one.
+///
+/// ```
+/// use shared::{Shared, SharedGraph};
+///
+/// let a = Shared::new(1);
+/// let json = serde_json::to_string(&SharedGraph(vec![a.clone(), a])).unwrap();
+/// let SharedGraph(values) = serde_json::from_str::<SharedGraph<Vec<Shared<i32>>>>(&json).unwrap();
+/// assert_eq!(values[0], values[1]);
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SharedGraph<T>(pub T);
+
+struct Session<M: 'static> {
+    table: &'static std::thread::LocalKey<RefCell<Option<M>>>,
+    outer: bool,
+}
+
+impl<M: Default> Session<M> {
+    fn enter(table: &'static std::thread::LocalKey<RefCell<Option<M>>>) -> Self {
+        let outer = table.with(|table| {
+            let mut table = table.borrow_mut();
+            let outer = table.is_none();
+            if outer {
+                *table = Some(M::default());
+            }
+            outer
+        });
+        Session { table, outer }
+    }
+}
+
+impl<M> Drop for Session<M> {
+    fn drop(&mut self) {
+        if self.outer {
+            // Dropping the handles may run arbitrary code, not while borrowed.
+            let table = self.table.with(|table| table.borrow_mut().take());
+            drop(table);
+        }
+    }
+}
+
+impl<T: Serialize> Serialize for SharedGraph<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let _session = Session::enter(&WRITTEN);
+        self.0.serialize(serializer)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for SharedGraph<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let _session = Session::enter(&READ);
+        T::deserialize(deserializer).map(SharedGraph)
+    }
+}
+
+enum Written {
+    First(u64),
+    Again(u64),
+}
+
+// `None` outside of a `SharedGraph`.
+fn written(addr: usize) -> Option<Written> {
+    WRITTEN.with(|written| {
+        let mut written = written.borrow_mut();
+        let written = written.as_mut()?;
+        let next = written.len() as u64;
+        Some(match written.get(&addr) {
+            Some(&id) => Written::Again(id),
+            None => {
+                written.insert(addr, next);
+                Written::First(next)
+            }
+        })
+    })
+}
+
+impl<T: ?Sized + Serialize, B: Backend<T>> Serialize for Shared<T, B> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let value = || B::borrow(&self.data).ok_or_else(|| ser::Error::custom("value is mutably borrowed"));
+        match written(Shared::addr(self)) {
+            None => value()?.serialize(serializer),
+            Some(Written::Again(id)) => serializer.serialize_newtype_variant(NAME, 1, "Ref", &id),
+            Some(Written::First(id)) => {
+                let mut variant = serializer.serialize_tuple_variant(NAME, 0, "Value", 2)?;
+                variant.serialize_field(&id)?;
+                variant.serialize_field(&*value()?)?;
+                variant.end()
+            }
+        }
+    }
+}
+
+impl<T: ?Sized + Serialize, B: WeakBackend<T>> Serialize for WeakShared<T, B> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let in_graph = WRITTEN.with(|written| written.borrow().is_some());
+        match self.upgrade() {
+            Some(shared) if in_graph => shared.serialize(serializer),
+            None if in_graph => serializer.serialize_unit_variant(NAME, 2, "Dangling"),
+            shared => shared.serialize(serializer),
+        }
+    }
+}
+
+enum Handle<T, B: Backend<T>> {
+    Strong(Shared<T, B>),
+    // Allocation that is still being deserialized.
+    Pending(u64),
+    Dangling,
+}
+
+fn in_graph() -> bool {
+    READ.with(|read| read.borrow().is_some())
+}
+
+fn insert(id: u64, slot: Slot) {
+    READ.with(|read| read.borrow_mut().as_mut().map(|read| read.insert(id, slot)));
+}
+
+impl<'de, T, B> Deserialize<'de> for Shared<T, B>
+where
+    T: Deserialize<'de> + 'static,
+    B: Backend<T> + 'static,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if !in_graph() {
+            return T::deserialize(deserializer).map(Shared::new_in);
+        }
+        match deserializer.deserialize_enum(NAME, VARIANTS, HandleVisitor(PhantomData))? {
+            Handle::Strong(shared) => Ok(shared),
+            Handle::Pending(_) => Err(de::Error::custom(
+                "strong handle to a value that is being deserialized, cycles need a weak handle",
+            )),
+            Handle::Dangling => Err(de::Error::custom("dangling handle where a strong one is expected")),
+        }
+    }
+}
+
+impl<'de, T, B> Deserialize<'de> for WeakShared<T, B>
+where
+    T: Deserialize<'de> + 'static,
+    B: WeakBackend<T> + 'static,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if !in_graph() {
+            // Nothing else owns the value.
+            return Option::<T>::deserialize(deserializer).map(|_| WeakShared::new());
+        }
+        match deserializer.deserialize_enum(NAME, VARIANTS, HandleVisitor::<T, B>(PhantomData))? {
+            Handle::Strong(shared) => Ok(shared.downgrade()),
+            Handle::Dangling => Ok(WeakShared::new()),
+            Handle::Pending(id) => {
+                READ.with(|read| match read.borrow().as_ref().and_then(|read| read.get(&id)) {
+                    Some(Slot::Pending(Some(weak))) => match weak.downcast_ref::<B::Weak>() {
+                        Some(weak) => Ok(WeakShared::from_weak(weak.clone())),
+                        None => Err(de::Error::custom(format_args!("mistyped id {}", id))),
+                    },
+                    Some(Slot::Pending(None)) => Err(de::Error::custom(
+                        "weak handle to a value that is being deserialized, the backend cannot create one yet",
+                    )),
+                    _ => Err(de::Error::custom(format_args!("mistyped id {}", id))),
+                })
+            }
+        }
+    }
+}
+
+enum Variant {
+    Value,
+    Ref,
+    Dangling,
+}
+
+impl<'de> Deserialize<'de> for Variant {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct VariantVisitor;
+
+        impl Visitor<'_> for VariantVisitor {
+            type Value = Variant;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("variant identifier")
+            }
+
+            fn visit_u64<E: de::Error>(self, index: u64) -> Result<Variant, E> {
+                match index {
+                    0 => Ok(Variant::Value),
+                    1 => Ok(Variant::Ref),
+                    2 => Ok(Variant::Dangling),
+                    _ => Err(E::invalid_value(de::Unexpected::Unsigned(index), &"variant index 0 <= i < 3")),
+                }
+            }
+
+            fn visit_str<E: de::Error>(self, name: &str) -> Result<Variant, E> {
+                match name {
+                    "Value" => Ok(Variant::Value),
+                    "Ref" => Ok(Variant::Ref),
+                    "Dangling" => Ok(Variant::Dangling),
+                    _ => Err(E::unknown_variant(name, VARIANTS)),
+                }
+            }
+        }
+
+        deserializer.deserialize_identifier(VariantVisitor)
+    }
+}
+
+struct HandleVisitor<T, B>(PhantomData<(T, B)>);
+
+impl<'de, T, B> Visitor<'de> for HandleVisitor<T, B>
+where
+    T: Deserialize<'de> + 'static,
+    B: Backend<T> + 'static,
+{
+    type Value = Handle<T, B>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a shared handle")
+    }
+
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<Self::Value, A::Error> {
+        match data.variant()? {
+            (Variant::Value, variant) => variant.tuple_variant(2, ValueVisitor(PhantomData)),
+            (Variant::Ref, variant) => {
+                let id: u64 = variant.newtype_variant()?;
+                let handle = READ.with(|read| {
+                    let read = read.borrow();
+                    match read.as_ref().and_then(|read| read.get(&id))? {
+                        Slot::Pending(_) => Some(Handle::Pending(id)),
+                        Slot::Done(shared) => {
+                            Some(Handle::Strong(shared.downcast_ref::<Shared<T, B>>()?.clone()))
+                        }
+                    }
+                });
+                handle.ok_or_else(|| de::Error::custom(format_args!("unknown or mistyped id {}", id)))
+            }
+            (Variant::Dangling, variant) => {
+                variant.unit_variant()?;
+                Ok(Handle::Dangling)
+            }
+        }
+    }
+}
+
+struct ValueVisitor<T, B>(PhantomData<(T, B)>);
+
+impl<'de, T, B> Visitor<'de> for ValueVisitor<T, B>
+where
+    T: Deserialize<'de> + 'static,
+    B: Backend<T> + 'static,
+{
+    type Value = Handle<T, B>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("an id and a value")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let id: u64 = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let data = B::try_new_cyclic_any(|weak| {
+            insert(id, Slot::Pending(weak));
+            seq.next_element_seed(PhantomData::<T>)?
+                .ok_or_else(|| de::Error::invalid_length(1, &"an id and a value"))
+        })?;
+        let shared = Shared::<T, B>::from_data(data);
+        insert(id, Slot::Done(Box::new(shared.clone())));
+        Ok(Handle::Strong(shared))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::packed::Packed;
+    use crate::sync::ArcRwLock;
+    use crate::{Shared, SharedGraph, WeakShared};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Node {
+        name: String,
+        parent: WeakShared<Node>,
+        children: Vec<Shared<Node>>,
+    }
+
+    fn tree() -> Shared<Node> {
+        let root = Shared::new(Node { name: "root".into(), parent: WeakShared::new(), children: Vec::new() });
+        for name in ["a", "b"] {
+            let child = Shared::new(Node { name: name.into(), parent: root.downgrade(), children: Vec::new() });
+            root.borrow_mut().children.push(child);
+        }
+        root
+    }
+
+    #[test]
+    fn sharing() {
+        let a = Shared::<_, ArcRwLock>::new_in(vec![1]);
+        let values = vec![a.clone(), Shared::new_in(vec![2]), a];
+
+        let json = serde_json::to_string(&SharedGraph(&values)).unwrap();
+        assert_eq!(json, r#"[{"Value":[0,[1]]},{"Value":[1,[2]]},{"Ref":0}]"#);
+        let SharedGraph(values) = serde_json::from_str::<SharedGraph<Vec<Shared<Vec<i32>, ArcRwLock>>>>(&json).unwrap();
+        assert_eq!(values[0], values[2]);
+        assert_eq!(values[0].use_count(), 2);
+        assert_eq!(*values[1].borrow(), [2]);
+
+        // Without a graph, every handle is a separate value.
+        let json = serde_json::to_string(&values).unwrap();
+        assert_eq!(json, "[[1],[2],[1]]");
+        let values: Vec<Shared<Vec<i32>>> = serde_json::from_str(&json).unwrap();
+        assert_ne!(values[0], values[2]);
+    }
+
+    #[test]
+    fn weak_cycles() {
+        let root = tree();
+        let json = serde_json::to_string(&SharedGraph(&root)).unwrap();
+        let SharedGraph(copy) = serde_json::from_str::<SharedGraph<Shared<Node>>>(&json).unwrap();
+
+        assert_eq!(copy.use_count(), 1);
+        assert_eq!(copy.parent.upgrade(), None);
+        let names: Vec<_> = copy.children.iter().map(|child| child.name.clone()).collect();
+        assert_eq!(names, ["a", "b"]);
+        for child in &copy.children {
+            assert_eq!(child.parent.upgrade(), Some(copy.clone()));
+        }
+        assert_eq!(serde_json::to_string(&SharedGraph(&copy)).unwrap(), json);
+    }
+
+    #[test]
+    fn errors() {
+        let json = r#"{"Value":[0,{"name":"root","parent":"Dangling","children":[{"Ref":0}]}]}"#;
+        let err = serde_json::from_str::<SharedGraph<Shared<Node>>>(json).err().unwrap();
+        assert!(err.to_string().contains("cycles need a weak handle"));
+
+        let json = r#"{"Value":[0,{"name":1}]}"#;
+        assert!(serde_json::from_str::<SharedGraph<Shared<Node>>>(json).is_err());
+        assert!(serde_json::from_str::<SharedGraph<Shared<i32>>>(r#"{"Ref":3}"#).is_err());
+
+        // The child holding a weak handle to the failed root outlives it.
+        let json = r#"{"Value":[0,{"name":"root","parent":"Dangling","children":[
+            {"Value":[1,{"name":"a","parent":{"Ref":0},"children":[]}]},
+            {"Value":[2,{"name":"b","parent":{"Ref":0},"children":[{"Ref":5}]}]}
+        ]}]}"#;
+        let err = serde_json::from_str::<SharedGraph<Shared<Node>>>(json).err().unwrap();
+        assert!(err.to_string().contains("unknown or mistyped id 5"));
+    }
+
+    #[test]
+    fn without_weak_handles() {
+        let a = Shared::<_, Packed>::new_in(vec![1]);
+        let json = serde_json::to_string(&SharedGraph(vec![a.clone(), a])).unwrap();
+        let SharedGraph(values) = serde_json::from_str::<SharedGraph<Vec<Shared<Vec<i32>, Packed>>>>(&json).unwrap();
+        assert_eq!(values[0], values[1]);
+
+        let value: Shared<Vec<i32>, Packed> = serde_json::from_str("[1, 2]").unwrap();
+        assert_eq!(*value, [1, 2]);
+    }
+
+    #[test]
+    #[cfg(feature = "weak")]
+    fn weak_cycles_need_a_weak_backend() {
+        #[derive(Deserialize)]
+        #[allow(dead_code)]
+        struct Node {
+            parent: WeakShared<Node, Packed>,
+            children: Vec<Shared<Node, Packed>>,
+        }
+
+        let json = r#"{"Value":[0,{"parent":"Dangling","children":[{"Value":[1,{"parent":{"Ref":0},"children":[]}]}]}]}"#;
+        let err = serde_json::from_str::<SharedGraph<Shared<Node, Packed>>>(json).err().unwrap();
+        assert!(err.to_string().contains("the backend cannot create one yet"));
+    }
+}
@@ -1,24 +1,217 @@
-#![feature(test)]
-extern crate test;
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+#![cfg_attr(feature = "nightly", feature(coerce_unsized, unsize))]
+
+extern crate alloc;
+
+use core::cell::RefCell;
+use alloc::boxed::Box;
+use alloc::rc::Rc;
+use core::ops::{Deref, DerefMut};
+use core::cmp::Ordering;
+use core::fmt;
+use core::hash::{Hash, Hasher};
+use core::mem::{self, ManuallyDrop};
+use core::ptr;
+
+#[cfg(all(feature = "std", debug_assertions))]
+mod alias;
+pub mod backend;
+mod borrow;
+mod by_value;
+#[cfg(feature = "tracking")]
+pub mod debug;
+mod forward;
+#[cfg(feature = "std")]
+pub mod gc;
+#[cfg(feature = "serde")]
+mod graph;
+pub mod packed;
+mod pin;
+#[cfg(feature = "std")]
+pub mod observe;
+mod proj;
+#[cfg(feature = "std")]
+pub mod reactive;
+mod reader;
+#[cfg(feature = "std")]
+pub mod sync;
+mod unsize;
+pub mod version;
+mod weak;
+
+pub use backend::{Backend, RcRefCell};
+pub use borrow::{BorrowError, BorrowMutError, SharedRef, SharedRefMut};
+pub use by_value::ByValue;
+#[cfg(feature = "std")]
+pub use gc::{GcShared, Trace};
+#[cfg(feature = "serde")]
+pub use graph::SharedGraph;
+#[cfg(feature = "derive")]
+pub use shared_derive::Trace;
+#[cfg(feature = "std")]
+pub use observe::ObservedShared;
+pub use packed::PackedShared;
+pub use pin::{PinnedRef, PinnedRefMut};
+pub use proj::SharedProj;
+pub use reader::SharedReader;
+#[cfg(feature = "std")]
+pub use sync::SyncShared;
+pub use weak::WeakShared;
+
+use backend::{PointerBackend, RawBackend};
+
+/// Handle to a shared value, with the same layout as the backend pointer
+/// (`Rc<RefCell<T>>` by default).
+///
+/// # Guards and `Deref`
+///
+/// `borrow` and `borrow_mut` return guards that hold the backend's borrow
+/// flag or lock, they are the checked way to reach the value. `Deref` and
+/// `DerefMut` (for a [`RawBackend`]) hand out plain references that nothing
+/// keeps track of: holding a `&T` from one handle while another handle
+/// mutates the value is undefined behaviour, as with `RefCell::as_ptr`.
+///
+/// With the `checked` feature, `Deref` and `DerefMut` panic if a conflicting
+/// guard is alive at the time of the call. References returned by earlier
+/// `deref`/`deref_mut` calls are invisible to that check, so it catches
+/// guard/deref conflicts only; code where two handles may access the value
+/// at once has to use guards. Debug builds remember the last `deref_mut` of
+/// each handle and list it when a conflict is reported.
+#[repr(transparent)]
+pub struct Shared<T: ?Sized, B: Backend<T> = RcRefCell> {
+    data: B::Data,
+}
 
-use std::cell::RefCell;
-use std::rc::Rc;
-use std::ops::{Deref, DerefMut};
-use std::fmt;
+impl<T> Shared<T> {
+    pub fn new(value: T) -> Self {
+        Shared::from_data(RcRefCell::new(value))
+    }
 
-type SharedData<T> = Rc<RefCell<T>>;
+    /// Moves a boxed value into a new allocation.
+    #[allow(clippy::boxed_local)]
+    pub fn from_box(value: Box<T>) -> Self {
+        Shared::new(*value)
+    }
+}
 
-pub struct Shared<T: ?Sized> {
-    data: SharedData<T>
+impl<T, B: Backend<T>> Shared<T, B> {
+    /// Allocates `value` with the backend `B`, e.g.
+    /// `Shared::<_, Packed>::new_in(value)`. `new` is reserved to the
+    /// default backend, so that `Shared::new` needs no annotations.
+    pub fn new_in(value: T) -> Self {
+        Shared::from_data(B::new(value))
+    }
 }
 
-impl<T> Shared<T> {
-    pub fn new(value: T) -> Self {
-        Shared { data: SharedData::new(RefCell::new(value)) }
+impl<T: ?Sized> Shared<T> {
+    /// Unwraps the `Rc<RefCell<T>>`, like `into_data`.
+    pub fn into_rc(self) -> Rc<RefCell<T>> {
+        self.into_data()
+    }
+
+    pub fn as_rc(&self) -> &Rc<RefCell<T>> {
+        &self.data
+    }
+}
+
+impl<T: ?Sized, B: Backend<T>> Shared<T, B> {
+    /// Wraps a backend pointer, e.g. an `Rc<RefCell<T>>` for the default backend.
+    pub fn from_data(data: B::Data) -> Self {
+        Shared { data }
+    }
+
+    /// Unwraps the backend pointer without touching the reference counts.
+    pub fn into_data(self) -> B::Data {
+        let this = ManuallyDrop::new(self);
+        unsafe { ptr::read(&this.data) }
     }
 
     pub fn use_count(&self) -> usize {
-        Rc::strong_count(&self.data)
+        B::strong_count(&self.data)
+    }
+
+    /// Immutably borrows the value, panicking if it is mutably borrowed.
+    #[track_caller]
+    pub fn borrow(&self) -> SharedRef<'_, T, B> {
+        match B::borrow(&self.data) {
+            Some(inner) => SharedRef::new(inner, Shared::addr(self)),
+            None => self.conflict(BorrowError::new()),
+        }
+    }
+
+    /// Mutably borrows the value, panicking if it is already borrowed.
+    #[track_caller]
+    pub fn borrow_mut(&self) -> SharedRefMut<'_, T, B> {
+        match B::borrow_mut(&self.data) {
+            Some(inner) => SharedRefMut::new(inner, Shared::addr(self)),
+            None => self.conflict(BorrowMutError::new()),
+        }
+    }
+
+    #[track_caller]
+    pub fn try_borrow(&self) -> Result<SharedRef<'_, T, B>, BorrowError> {
+        match B::try_borrow(&self.data) {
+            Some(inner) => Ok(SharedRef::new(inner, Shared::addr(self))),
+            None => Err(BorrowError::new()),
+        }
+    }
+
+    #[track_caller]
+    pub fn try_borrow_mut(&self) -> Result<SharedRefMut<'_, T, B>, BorrowMutError> {
+        match B::try_borrow_mut(&self.data) {
+            Some(inner) => Ok(SharedRefMut::new(inner, Shared::addr(self))),
+            None => Err(BorrowMutError::new()),
+        }
+    }
+
+    /// Returns the value if `this` is the only strong handle, otherwise
+    /// gives the handle back.
+    pub fn try_unwrap(this: Self) -> Result<T, Self> where T: Sized {
+        B::try_unwrap(this.into_data()).map_err(Shared::from_data)
+    }
+
+    /// Returns the value if `this` is the last strong handle. When every
+    /// handle is passed to `into_inner`, exactly one call returns `Some`.
+    pub fn into_inner(this: Self) -> Option<T> where T: Sized {
+        B::into_inner(this.into_data())
+    }
+
+    /// Mutable access without a guard if `this` is the only handle, weak
+    /// handles included.
+    pub fn get_mut(this: &mut Self) -> Option<&mut T> {
+        B::get_mut(&mut this.data)
+    }
+
+    /// Mutable access to a value owned by `this` alone, cloning it into a
+    /// fresh allocation first if other handles (strong or weak) exist.
+    /// Other handles, weak ones included, stay with the old allocation.
+    #[track_caller]
+    pub fn make_mut(this: &mut Self) -> &mut T where T: Clone {
+        if B::get_mut(&mut this.data).is_none() {
+            let value = this.borrow().clone();
+            *this = Shared::from_data(B::new(value));
+        }
+        B::get_mut(&mut this.data).expect("freshly allocated value is unique")
+    }
+
+    /// Whether both handles point to the same allocation.
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        Shared::addr(this) == Shared::addr(other)
+    }
+
+    /// Address of the allocation, the identity used by `PartialEq`, `Hash`
+    /// and `Ord`.
+    pub fn addr(this: &Self) -> usize {
+        B::addr(&this.data) as usize
+    }
+
+    #[cold]
+    #[track_caller]
+    fn conflict(&self, err: impl fmt::Display) -> ! {
+        #[cfg(all(feature = "std", debug_assertions))]
+        panic!("{}{}", err, alias::report(Shared::addr(self)));
+        #[cfg(not(all(feature = "std", debug_assertions)))]
+        panic!("{}", err);
     }
 }
 
@@ -28,79 +221,184 @@ impl<T> From<T> for Shared<T> {
     }
 }
 
-impl<T: ?Sized> From<SharedData<T>> for Shared<T> {
-    fn from(data: SharedData<T>) -> Self {
-        Shared { data }
+impl<T: ?Sized> From<Rc<RefCell<T>>> for Shared<T> {
+    fn from(data: Rc<RefCell<T>>) -> Self {
+        Shared::from_data(data)
     }
 }
 
-impl<T: ?Sized> Clone for Shared<T> {
+impl<T: ?Sized, B: Backend<T>> Clone for Shared<T, B> {
     fn clone(&self) -> Self {
-        From::from(self.data.clone())
+        Shared::from_data(self.data.clone())
     }
 }
 
-impl<T: Default> Default for Shared<T> {
+impl<T: Default, B: Backend<T>> Default for Shared<T, B> {
     fn default() -> Self {
-        Shared::new(Default::default())
+        Shared::new_in(T::default())
     }
 }
 
-impl<T: ?Sized> Deref for Shared<T> {
+impl<T: ?Sized, B: RawBackend<T>> Deref for Shared<T, B> {
     type Target = T;
 
+    #[cfg_attr(feature = "checked", track_caller)]
     fn deref(&self) -> &Self::Target {
-        unsafe { &*self.data.as_ptr() }
+        if !B::on_deref(&self.data) {
+            self.conflict("cannot dereference `Shared`: value is already mutably borrowed");
+        }
+        unsafe { &*B::as_ptr(&self.data) }
     }
 }
 
-impl<T: ?Sized> DerefMut for Shared<T> {
+impl<T: ?Sized, B: RawBackend<T>> DerefMut for Shared<T, B> {
+    #[cfg_attr(any(feature = "checked", all(feature = "std", debug_assertions)), track_caller)]
     fn deref_mut(&mut self) -> &mut Self::Target {
-        unsafe { &mut *self.data.as_ptr() }
+        if !B::on_deref_mut(&self.data) {
+            self.conflict("cannot mutably dereference `Shared`: value is already borrowed");
+        }
+        #[cfg(all(feature = "std", debug_assertions))]
+        alias::deref_mut(Shared::addr(self), self as *const Self as usize, core::panic::Location::caller());
+        unsafe { &mut *B::as_ptr(&self.data) }
     }
 }
 
-impl<T: ?Sized> AsRef<T> for Shared<T> {
+impl<T: ?Sized, B: RawBackend<T>> AsRef<T> for Shared<T, B> {
     fn as_ref(&self) -> &T {
-        &**self
+        self
     }
 }
 
-impl<T: ?Sized> AsMut<T> for Shared<T> {
+impl<T: ?Sized, B: RawBackend<T>> AsMut<T> for Shared<T, B> {
     fn as_mut(&mut self) -> &mut T {
-        &mut **self
+        self
+    }
+}
+
+impl<T: ?Sized, B: RawBackend<T>> Shared<T, B> {
+    /// Pointer to the value, valid as long as a handle is alive.
+    pub fn as_ptr(this: &Self) -> *const T {
+        B::as_ptr(&this.data)
+    }
+}
+
+impl<T: ?Sized, B: PointerBackend<T>> Shared<T, B> {
+    /// Gives up the handle without touching the counts, e.g. to pass it to C
+    /// as `void*`. Unlike `as_ptr`, the pointer is to the cell holding the
+    /// value (the `RefCell<T>` of the default backend).
+    pub fn into_raw(this: Self) -> *const B::Raw {
+        B::into_raw(this.into_data())
+    }
+
+    /// Takes back a handle given up by `into_raw`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must come from `into_raw` with the same `T` and backend, and
+    /// every handle given up that way is taken back at most once.
+    pub unsafe fn from_raw(ptr: *const B::Raw) -> Self {
+        Shared::from_data(B::from_raw(ptr))
+    }
+
+    /// Adds a handle to the allocation behind `ptr`, to be taken back by
+    /// `from_raw` or dropped by `decrement_strong_count`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must come from `into_raw` and its handle not be taken back yet.
+    pub unsafe fn increment_strong_count(ptr: *const B::Raw) {
+        let data = ManuallyDrop::new(B::from_raw(ptr));
+        mem::forget(B::Data::clone(&data));
+    }
+
+    /// Drops a handle given up by `into_raw`.
+    ///
+    /// # Safety
+    ///
+    /// Same as `from_raw`.
+    pub unsafe fn decrement_strong_count(ptr: *const B::Raw) {
+        drop(B::from_raw(ptr));
     }
 }
 
-impl<T: ?Sized> PartialEq for Shared<T> {
+// Handles compare, hash and order by allocation, see `ByValue` for
+// comparisons of the values themselves.
+impl<T: ?Sized, B: Backend<T>> PartialEq for Shared<T, B> {
     fn eq(&self, other: &Self) -> bool {
-         self.as_ref() as *const T == other.as_ref() as *const T
+        Shared::ptr_eq(self, other)
     }
 }
 
-impl<T> Eq for Shared<T> { }
+impl<T: ?Sized, B: Backend<T>> Eq for Shared<T, B> { }
+
+impl<T: ?Sized, B: Backend<T>> Hash for Shared<T, B> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Shared::addr(self).hash(state)
+    }
+}
 
-impl<T: ?Sized + fmt::Debug> fmt::Debug for Shared<T> {
+impl<T: ?Sized, B: Backend<T>> PartialOrd for Shared<T, B> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T: ?Sized, B: Backend<T>> Ord for Shared<T, B> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        Shared::addr(self).cmp(&Shared::addr(other))
+    }
+}
+
+impl<T: ?Sized, B: Backend<T>> fmt::Debug for Shared<T, B> where B::Data: fmt::Debug {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.write_str(format!("Shared {{ data: {:?} }}", self.data).as_str())
+        write!(f, "Shared {{ data: {:?} }}", self.data)
     }
 }
 
+/// Creates a `Shared` with the default backend.
+///
+/// - `shared!(value)` wraps a single value,
+/// - `shared![a, b, c]` and `shared![value; n]` build a `Shared<Vec<_>>`,
+///   a one-element list needs a trailing comma: `shared![a,]`,
+/// - `shared!{k => v, ...}` builds a `Shared<BTreeMap<_, _>>`, with or
+///   without the `std` feature.
+///
+/// ```
+/// let scores = shared::shared!{"a" => 1.5, "b" => 2.0};
+/// let names = shared::shared!["a", "b"];
+/// assert_eq!(scores[names[1]], 2.0);
+/// ```
 #[macro_export]
 macro_rules! shared {
+    ($($key:expr => $value:expr),+ $(,)?) => {
+        $crate::Shared::new({
+            let mut map = $crate::__private::Map::new();
+            $(map.insert($key, $value);)+
+            map
+        })
+    };
+    ($value:expr; $n:expr) => {
+        $crate::Shared::new($crate::__private::vec![$value; $n])
+    };
     ($value:expr) => {
-        Shared::new($value)
+        $crate::Shared::new($value)
     };
     ($($values:expr),+ $(,)?) => {
-        Shared::<&[i32]>::new(&[$($values),+]);
+        $crate::Shared::new($crate::__private::vec![$($values),+])
     };
 }
 
+#[doc(hidden)]
+pub mod __private {
+    pub use alloc::vec;
+    pub use alloc::collections::BTreeMap as Map;
+    pub use crate::proj::Project;
+}
+
 #[cfg(test)]
 mod tests {
     use crate::Shared;
     use std::any::Any;
-    use test::Bencher;
 
     #[test]
     fn it_works() {
@@ -128,19 +426,32 @@ mod tests {
 
     #[test]
     fn storage_arrays() {
-        let x = Shared::<&[i32]>::new(&[1, 2, 3]);
+        let x = Shared::new(vec![1, 2, 3]);
         let y = shared!{1, 2, 3};
         assert_eq!(x.type_id(), y.type_id());
+        assert_eq!(*x, *y);
     }
 
     #[test]
     fn array_storage() {
-        let x = Shared::<&[i32]>::new(&[1, 2, 3]);
-        let y = shared!{1, 2, 3};
-        assert_eq!(x.type_id(), y.type_id());
+        let x = shared![0.5; 3];
+        let y = shared!["a", "b",];
+        let z = shared![String::from("a"),];
+        assert_eq!(*x, [0.5, 0.5, 0.5]);
+        assert_eq!(*y, ["a", "b"]);
+        assert_eq!(*z, ["a"]);
+    }
+
+    #[test]
+    fn map_storage() {
+        let map = shared!{"a" => 1, "b" => 2};
+        let _: &std::collections::BTreeMap<&str, i32> = &map;
+        assert_eq!(map["b"], 2);
+        assert_eq!(map.len(), 2);
     }
 
     #[test]
+    #[allow(unused_mut, array_into_iter, clippy::into_iter_on_ref)]
     fn example() {
         let mut data = [
             shared!(228),
@@ -166,23 +477,208 @@ mod tests {
         println!("{:?}", data)
     }
 
-    #[bench]
-    fn compare_shared_vec(b: &mut Bencher) {
-        b.iter(|| {
-            let _array: Vec<_> = (0..1000).into_iter()
-                .map(|i| shared!(i))
-                .collect();
-            let _clone = _array.clone();
-        });
-    }
-
-    #[bench]
-    fn compare_data_vec(b: &mut Bencher) {
-        b.iter(|| {
-            let _array: Vec<_> = (0..1000).into_iter()
-                .map(|i| i)
-                .collect();
-            let _clone = _array.clone();
-        });
+    #[test]
+    fn borrow_guards() {
+        let a = shared!(1);
+        let b = a.clone();
+
+        *b.borrow_mut() += 1;
+        {
+            let x = a.borrow();
+            let y = b.borrow();
+            assert_eq!(*x + *y, 4);
+            assert!(a.try_borrow_mut().is_err());
+        }
+        let guard = a.borrow_mut();
+        assert!(b.try_borrow().is_err());
+        assert!(b.try_borrow_mut().is_err());
+        drop(guard);
+        assert!(b.try_borrow_mut().is_ok());
+    }
+
+    #[test]
+    #[should_panic(expected = "already borrowed")]
+    fn borrow_mut_while_borrowed() {
+        let a = shared!(1);
+        let _x = a.borrow();
+        let _y = a.borrow_mut();
+    }
+
+    #[test]
+    #[cfg(feature = "checked")]
+    #[should_panic(expected = "cannot mutably dereference")]
+    fn checked_deref_mut_while_borrowed() {
+        let a = shared!(1);
+        let mut b = a.clone();
+        let _guard = a.borrow();
+        *b += 1;
+    }
+
+    #[test]
+    #[cfg(feature = "checked")]
+    #[should_panic(expected = "cannot dereference")]
+    fn checked_deref_while_borrowed_mut() {
+        let a = shared!(1);
+        let b = a.clone();
+        let _guard = a.borrow_mut();
+        let _ = *b;
+    }
+
+    #[test]
+    #[cfg(all(feature = "checked", feature = "std", debug_assertions))]
+    fn conflict_reports_live_borrows() {
+        let a = shared!(1);
+        let mut b = a.clone();
+        let _guard = a.borrow_mut();
+        let line = line!() - 1;
+
+        let err = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            *b += 1;
+        })).unwrap_err();
+        let msg = err.downcast_ref::<String>().unwrap();
+        assert!(msg.contains("conflicting mutable borrow taken at"));
+        assert!(msg.contains(&format!("{}:{}", file!(), line)));
+    }
+
+    #[test]
+    #[cfg(all(feature = "std", debug_assertions))]
+    fn deref_mut_sites_are_reported() {
+        let mut a = shared!(1);
+        let mut b = a.clone();
+        *a += 1;
+        let line_a = line!() - 1;
+        *b += 1;
+        let line_b = line!() - 1;
+
+        let _guard = b.borrow_mut();
+        let err = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _ = a.borrow();
+        })).unwrap_err();
+        let msg = err.downcast_ref::<String>().unwrap();
+        assert!(msg.contains("conflicting mutable borrow taken at"));
+        assert_eq!(msg.matches("last `deref_mut` of the handle").count(), 2);
+        assert!(msg.contains(&format!("{}:{}", file!(), line_a)));
+        assert!(msg.contains(&format!("{}:{}", file!(), line_b)));
+    }
+
+    #[test]
+    fn dropck() {
+        let (handles, x);
+        x = 1;
+        handles = vec![Shared::new(&x)];
+        assert_eq!(**handles[0], 1);
+    }
+
+    #[test]
+    fn unwrap() {
+        let a = shared!(String::from("a"));
+        let b = a.clone();
+
+        let a = Shared::try_unwrap(a).unwrap_err();
+        assert_eq!(Shared::into_inner(b), None);
+        assert_eq!(Shared::try_unwrap(a).unwrap(), "a");
+    }
+
+    #[test]
+    fn get_mut() {
+        let mut a = shared!(1);
+        *Shared::get_mut(&mut a).unwrap() += 1;
+
+        let b = a.clone();
+        assert!(Shared::get_mut(&mut a).is_none());
+        drop(b);
+        let weak = a.downgrade();
+        assert!(Shared::get_mut(&mut a).is_none());
+        drop(weak);
+        assert_eq!(Shared::get_mut(&mut a), Some(&mut 2));
+    }
+
+    #[test]
+    fn make_mut() {
+        let mut a = shared![1, 2];
+        let b = a.clone();
+        let weak = a.downgrade();
+
+        Shared::make_mut(&mut a).push(3);
+        assert_eq!(*a, [1, 2, 3]);
+        assert_eq!(*b, [1, 2]);
+        assert_eq!(weak.upgrade(), Some(b));
+
+        let before = Shared::addr(&a);
+        Shared::make_mut(&mut a).push(4);
+        assert_eq!(Shared::addr(&a), before);
+    }
+
+    #[test]
+    fn identity() {
+        use std::collections::{BTreeSet, HashMap};
+
+        let a = shared!(1);
+        let b = shared!(1);
+        let mut names = HashMap::new();
+        names.insert(a.clone(), "a");
+        names.insert(b.clone(), "b");
+
+        assert!(Shared::ptr_eq(&a, &a.clone()));
+        assert_eq!(names[&a], "a");
+        assert_eq!(names[&b], "b");
+        assert_eq!(Shared::as_ptr(&a), &*a as *const i32);
+        assert_eq!(a.cmp(&b), Shared::addr(&a).cmp(&Shared::addr(&b)));
+        assert_eq!([a.clone(), b, a].iter().collect::<BTreeSet<_>>().len(), 2);
+    }
+
+    #[test]
+    fn raw() {
+        use crate::packed::Packed;
+        use std::cell::RefCell;
+        use std::ffi::c_void;
+        use std::mem::size_of;
+        use std::rc::Rc;
+
+        let a = shared!(String::from("a"));
+        let user_data = Shared::into_raw(a.clone()) as *mut c_void;
+        unsafe {
+            Shared::<String>::increment_strong_count(user_data as *const _);
+            assert_eq!(a.use_count(), 3);
+            Shared::<String>::decrement_strong_count(user_data as *const _);
+            let b = Shared::<String>::from_raw(user_data as *const _);
+            b.borrow_mut().push('b');
+            assert_eq!(b, a);
+        }
+        assert_eq!(a.use_count(), 1);
+        assert_eq!(*a, "ab");
+
+        let d = Shared::<_, Packed>::new_in(vec![2]);
+        let ptr = Shared::into_raw(d);
+        let d = unsafe { Shared::<Vec<i32>, Packed>::from_raw(ptr) };
+        #[cfg(feature = "std")]
+        {
+            use crate::sync::ArcMutex;
+
+            let c = Shared::<_, ArcMutex>::new_in(1);
+            let c = unsafe { Shared::<i32, ArcMutex>::from_raw(Shared::into_raw(c)) };
+            assert_eq!(*c.borrow() + d[0], 3);
+        }
+        assert_eq!(Shared::try_unwrap(d).unwrap(), [2]);
+        assert_eq!(size_of::<Shared<str>>(), size_of::<Rc<RefCell<str>>>());
+    }
+
+    #[test]
+    fn rc_interop() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        let rc = Rc::new(RefCell::new(1));
+        let a = Shared::<i32>::from(rc.clone());
+        *a.borrow_mut() += 1;
+
+        assert!(Rc::ptr_eq(a.as_rc(), &rc));
+        assert!(Rc::ptr_eq(&a.clone().into_rc(), &rc));
+        assert_eq!(*rc.borrow(), 2);
+        assert_eq!(*Shared::from_box(Box::new(3)), 3);
+
+        let b = Shared::from(4);
+        let c: Shared<_> = 5.into();
+        assert_eq!(*b + *c, 9);
     }
 }
@@ -0,0 +1,340 @@
+//! Change notification for `Shared` values.
+//!
+//! An [`ObservedShared`] is only reachable through guards, and dropping a
+//! mutable guard notifies the callbacks registered with
+//! [`subscribe`](Shared::subscribe). Callbacks run after the write, with the
+//! value borrowed, so they cannot call `borrow_mut` on the same handle; use
+//! [`update`](Shared::update) instead, which is applied once the current
+//! round of callbacks is done and triggers another one. Notifications never
+//! nest.
+//!
+//! Writes inside [`batch`] are coalesced into one notification per value at
+//! the end of the batch.
+
+use std::cell::{Cell, Ref, RefCell, RefMut};
+use std::fmt;
+use std::mem::ManuallyDrop;
+use std::ops::{Deref, DerefMut};
+use std::rc::{Rc, Weak};
+
+use crate::backend::{pointer_backend, Backend, PointerBackend, WeakBackend};
+use crate::Shared;
+
+/// `Shared` notifying subscribers when it is mutably borrowed.
+pub type ObservedShared<T> = Shared<T, Observed>;
+
+/// `Rc<ObservedCell<T>>`, a `RefCell` with a list of subscribers.
+pub enum Observed {}
+
+type Callback<T> = Rc<dyn Fn(&T)>;
+type Update<T> = Box<dyn FnOnce(&mut T)>;
+
+pub struct ObservedCell<T> {
+    subscribers: RefCell<Vec<(u64, Callback<T>)>>,
+    next_id: Cell<u64>,
+    pending: RefCell<Vec<Update<T>>>,
+    notifying: Cell<bool>,
+    dirty: Cell<bool>,
+    value: RefCell<T>,
+}
+
+impl<T> ObservedCell<T> {
+    fn new(value: T) -> Self {
+        ObservedCell {
+            subscribers: RefCell::new(Vec::new()),
+            next_id: Cell::new(0),
+            pending: RefCell::new(Vec::new()),
+            notifying: Cell::new(false),
+            dirty: Cell::new(false),
+            value: RefCell::new(value),
+        }
+    }
+
+    fn subscribed(&self, id: u64) -> bool {
+        self.subscribers.borrow().iter().any(|(other, _)| *other == id)
+    }
+
+    fn notify(&self) {
+        if self.notifying.replace(true) {
+            self.dirty.set(true);
+            return;
+        }
+        let _reset = OnDrop::new(|| self.notifying.set(false));
+        loop {
+            self.dirty.set(false);
+            let subscribers = self.subscribers.borrow().clone();
+            for (id, callback) in subscribers {
+                if self.subscribed(id) {
+                    callback(&self.value.borrow());
+                }
+            }
+            let pending = self.pending.take();
+            for update in pending {
+                update(&mut self.value.borrow_mut());
+                self.dirty.set(true);
+            }
+            if !self.dirty.get() {
+                break;
+            }
+        }
+    }
+}
+
+/// Runs a closure when dropped, including during unwinding.
+pub(crate) struct OnDrop<F: FnOnce()>(Option<F>);
+
+impl<F: FnOnce()> OnDrop<F> {
+    pub(crate) fn new(f: F) -> Self {
+        OnDrop(Some(f))
+    }
+}
+
+impl<F: FnOnce()> Drop for OnDrop<F> {
+    fn drop(&mut self) {
+        if let Some(f) = self.0.take() {
+            f();
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for ObservedCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ObservedCell").field("value", &self.value).finish()
+    }
+}
+
+trait Observable {
+    fn notify(&self);
+    fn discard(&self);
+    fn unsubscribe(&self, id: u64);
+}
+
+impl<T> Observable for ObservedCell<T> {
+    fn notify(&self) {
+        ObservedCell::notify(self)
+    }
+
+    fn discard(&self) {
+        self.dirty.set(false);
+    }
+
+    fn unsubscribe(&self, id: u64) {
+        self.subscribers.borrow_mut().retain(|(other, _)| *other != id);
+    }
+}
+
+thread_local! {
+    static BATCH: RefCell<Option<Vec<Rc<dyn Observable>>>> = const { RefCell::new(None) };
+}
+
+fn changed<T: 'static>(cell: &Rc<ObservedCell<T>>) {
+    let batched = BATCH.with(|batch| match &mut *batch.borrow_mut() {
+        Some(pending) => {
+            if !cell.dirty.replace(true) {
+                pending.push(cell.clone());
+            }
+            true
+        }
+        None => false,
+    });
+    if !batched {
+        cell.notify();
+    }
+}
+
+/// Runs `f`, delaying notifications for the values it mutates until it
+/// returns. Nested batches are merged into the outermost one.
+pub fn batch<R>(f: impl FnOnce() -> R) -> R {
+    let outer = BATCH.with(|batch| {
+        let mut batch = batch.borrow_mut();
+        let outer = batch.is_none();
+        if outer {
+            *batch = Some(Vec::new());
+        }
+        outer
+    });
+    let _flush = OnDrop::new(|| {
+        if !outer {
+            return;
+        }
+        let pending = BATCH.with(|batch| batch.borrow_mut().take()).unwrap_or_default();
+        for cell in pending {
+            if std::thread::panicking() {
+                cell.discard();
+            } else {
+                cell.notify();
+            }
+        }
+    });
+    f()
+}
+
+/// Mutable guard of an [`ObservedShared`], notifying subscribers on drop.
+pub struct ObservedRefMut<'a, T: 'static> {
+    inner: ManuallyDrop<RefMut<'a, T>>,
+    cell: &'a Rc<ObservedCell<T>>,
+}
+
+impl<T: 'static> Deref for ObservedRefMut<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T: 'static> DerefMut for ObservedRefMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+}
+
+impl<T: 'static> Drop for ObservedRefMut<'_, T> {
+    fn drop(&mut self) {
+        unsafe { ManuallyDrop::drop(&mut self.inner) };
+        changed(self.cell);
+    }
+}
+
+impl<T: 'static> Backend<T> for Observed {
+    type Data = Rc<ObservedCell<T>>;
+    type Ref<'a> = Ref<'a, T>;
+    type RefMut<'a> = ObservedRefMut<'a, T>;
+
+    pointer_backend!(Rc, ObservedCell);
+
+    fn try_borrow(data: &Self::Data) -> Option<Self::Ref<'_>> {
+        data.value.try_borrow().ok()
+    }
+
+    fn try_borrow_mut(data: &Self::Data) -> Option<Self::RefMut<'_>> {
+        let inner = data.value.try_borrow_mut().ok()?;
+        Some(ObservedRefMut { inner: ManuallyDrop::new(inner), cell: data })
+    }
+
+    fn try_unwrap(data: Self::Data) -> Result<T, Self::Data> {
+        Rc::try_unwrap(data).map(|cell| cell.value.into_inner())
+    }
+
+    fn get_mut(data: &mut Self::Data) -> Option<&mut T> {
+        Rc::get_mut(data).map(|cell| cell.value.get_mut())
+    }
+}
+
+impl<T: 'static> WeakBackend<T> for Observed {
+    pointer_backend!(weak Rc, Weak, ObservedCell);
+}
+
+impl<T: 'static> PointerBackend<T> for Observed {
+    pointer_backend!(raw Rc, ObservedCell);
+}
+
+/// Registration of a callback, unsubscribing it when dropped.
+#[must_use = "dropping a `Subscription` unsubscribes the callback"]
+pub struct Subscription {
+    cell: Weak<dyn Observable>,
+    id: u64,
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        if let Some(cell) = self.cell.upgrade() {
+            cell.unsubscribe(self.id);
+        }
+    }
+}
+
+impl fmt::Debug for Subscription {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Subscription").field("id", &self.id).finish()
+    }
+}
+
+impl<T: 'static> Shared<T, Observed> {
+    /// Calls `f` with the new value after every mutable borrow.
+    pub fn subscribe(&self, f: impl Fn(&T) + 'static) -> Subscription {
+        let id = self.data.next_id.get();
+        self.data.next_id.set(id + 1);
+        self.data.subscribers.borrow_mut().push((id, Rc::new(f)));
+        let cell: Rc<dyn Observable> = self.data.clone();
+        Subscription { cell: Rc::downgrade(&cell), id }
+    }
+
+    /// Mutates the value and notifies, or queues the change if it is made
+    /// from a callback of this value.
+    #[track_caller]
+    pub fn update(&self, f: impl FnOnce(&mut T) + 'static) {
+        if self.data.notifying.get() {
+            self.data.pending.borrow_mut().push(Box::new(f));
+        } else {
+            f(&mut self.borrow_mut());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::observe::{batch, ObservedShared, Subscription};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn log<T: Clone + 'static>(shared: &ObservedShared<T>) -> (Rc<RefCell<Vec<T>>>, Subscription) {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let subscription = shared.subscribe({
+            let log = log.clone();
+            move |value: &T| log.borrow_mut().push(value.clone())
+        });
+        (log, subscription)
+    }
+
+    #[test]
+    fn subscribe() {
+        let a = ObservedShared::new_in(1);
+        let (log, subscription) = log(&a);
+
+        *a.borrow_mut() += 1;
+        let _ = *a.borrow();
+        *a.clone().borrow_mut() += 1;
+        assert_eq!(*log.borrow(), [2, 3]);
+
+        drop(subscription);
+        *a.borrow_mut() += 1;
+        assert_eq!(*log.borrow(), [2, 3]);
+    }
+
+    #[test]
+    fn reentrant_update() {
+        let a = ObservedShared::new_in(0);
+        let (log, _subscription) = log(&a);
+        let _clamp = a.subscribe({
+            let a = a.downgrade();
+            move |value| {
+                if *value > 10 {
+                    a.upgrade().unwrap().update(|value| *value = 10);
+                }
+            }
+        });
+
+        a.update(|value| *value = 20);
+        assert_eq!(*a.borrow(), 10);
+        assert_eq!(*log.borrow(), [20, 10]);
+        assert_eq!(a.use_count(), 1);
+    }
+
+    #[test]
+    fn batched() {
+        let a = ObservedShared::new_in(0);
+        let b = ObservedShared::new_in(0);
+        let (log_a, _sa) = log(&a);
+        let (log_b, _sb) = log(&b);
+
+        batch(|| {
+            *a.borrow_mut() += 1;
+            batch(|| *a.borrow_mut() += 1);
+            *b.borrow_mut() += 1;
+            assert!(log_a.borrow().is_empty());
+        });
+        assert_eq!(*log_a.borrow(), [2]);
+        assert_eq!(*log_b.borrow(), [1]);
+    }
+}
@@ -0,0 +1,451 @@
+//! Backends with a smaller allocation header than `Rc<RefCell<T>>`.
+//!
+//! `Rc` always stores a strong and a weak count next to the `RefCell` borrow
+//! flag. [`PackedRc`] keeps only the strong count, plus the weak count when
+//! the `weak` feature is enabled, so a [`Packed`] allocation is
+//! `strong + flag + value` and a [`PackedCell`] one (for `Copy` data, see
+//! `RcCell`) is `strong + value`.
+//!
+//! The default `Shared` keeps the `Rc<RefCell<T>>` layout, which `as_rc`,
+//! `into_rc` and the `Rc` conversions hand out; [`PackedShared`] opts into
+//! the smaller header.
+
+use core::cell::{Cell, Ref, RefCell, RefMut};
+use core::fmt;
+use core::marker::PhantomData;
+use core::mem::{self, ManuallyDrop};
+use core::ops::Deref;
+use core::ptr::{self, NonNull};
+
+use alloc::boxed::Box;
+#[cfg(feature = "std")]
+use std::process::abort;
+
+use crate::backend::{Backend, CellRef, CellRefMut, PointerBackend, RawBackend};
+#[cfg(feature = "weak")]
+use crate::backend::WeakBackend;
+use crate::Shared;
+
+#[repr(C)]
+struct PackedBox<C: ?Sized> {
+    strong: Cell<usize>,
+    // Counts weak handles plus one for all strong handles together, like `Rc`.
+    #[cfg(feature = "weak")]
+    weak: Cell<usize>,
+    value: ManuallyDrop<C>,
+}
+
+/// Reference-counted pointer without a weak count, unless the `weak`
+/// feature is enabled.
+pub struct PackedRc<C: ?Sized> {
+    ptr: NonNull<PackedBox<C>>,
+    _marker: PhantomData<PackedBox<C>>,
+}
+
+impl<C> PackedRc<C> {
+    pub fn new(value: C) -> Self {
+        let inner = Box::new(PackedBox {
+            strong: Cell::new(1),
+            #[cfg(feature = "weak")]
+            weak: Cell::new(1),
+            value: ManuallyDrop::new(value),
+        });
+        PackedRc { ptr: NonNull::from(Box::leak(inner)), _marker: PhantomData }
+    }
+
+    /// Returns the value if `this` is the only strong handle.
+    pub fn try_unwrap(this: Self) -> Result<C, Self> {
+        if this.inner().strong.get() != 1 {
+            return Err(this);
+        }
+        let this = ManuallyDrop::new(this);
+        let inner = this.inner();
+        inner.strong.set(0);
+        let value = unsafe { ptr::read(&*inner.value) };
+        unsafe { PackedRc::release(this.ptr) };
+        Ok(value)
+    }
+}
+
+impl<C> PackedRc<C> {
+    /// Consumes the handle, returning a pointer to the value.
+    pub fn into_raw(this: Self) -> *const C {
+        let this = ManuallyDrop::new(this);
+        // Derived from the allocation, `from_raw` goes back to its start.
+        unsafe { ptr::addr_of!((*this.ptr.as_ptr()).value) as *const C }
+    }
+
+    /// # Safety
+    ///
+    /// `ptr` must come from `into_raw`, and is given up by the call.
+    pub unsafe fn from_raw(ptr: *const C) -> Self {
+        let inner = (ptr as *const u8).sub(mem::offset_of!(PackedBox<C>, value)) as *mut PackedBox<C>;
+        PackedRc { ptr: NonNull::new_unchecked(inner), _marker: PhantomData }
+    }
+}
+
+impl<C: ?Sized> PackedRc<C> {
+    fn inner(&self) -> &PackedBox<C> {
+        unsafe { self.ptr.as_ref() }
+    }
+
+    pub fn strong_count(this: &Self) -> usize {
+        this.inner().strong.get()
+    }
+
+    pub fn as_ptr(this: &Self) -> *const C {
+        &*this.inner().value
+    }
+
+    /// Mutable access if `this` is the only handle, weak ones included.
+    pub fn get_mut(this: &mut Self) -> Option<&mut C> {
+        let inner = this.inner();
+        #[cfg(feature = "weak")]
+        let unique = inner.strong.get() == 1 && inner.weak.get() == 1;
+        #[cfg(not(feature = "weak"))]
+        let unique = inner.strong.get() == 1;
+        if unique {
+            Some(unsafe { &mut (*this.ptr.as_ptr()).value })
+        } else {
+            None
+        }
+    }
+
+    /// Drops the implicit weak handle held by the strong ones, freeing the
+    /// allocation if no weak handle is left. The value must already be gone.
+    unsafe fn release(ptr: NonNull<PackedBox<C>>) {
+        #[cfg(feature = "weak")]
+        {
+            let weak = &ptr.as_ref().weak;
+            weak.set(weak.get() - 1);
+            if weak.get() != 0 {
+                return;
+            }
+        }
+        drop(Box::from_raw(ptr.as_ptr()));
+    }
+}
+
+fn increment(count: &Cell<usize>) {
+    // Same policy as `Rc`: an overflowing count can only come from leaked
+    // handles, and wrapping would lead to a use-after-free.
+    let count_ = count.get().wrapping_add(1);
+    if count_ == 0 {
+        abort();
+    }
+    count.set(count_);
+}
+
+// `core` cannot abort, a panic is the closest it gets.
+#[cfg(not(feature = "std"))]
+fn abort() -> ! {
+    panic!("reference count overflow")
+}
+
+impl<C: ?Sized> Clone for PackedRc<C> {
+    fn clone(&self) -> Self {
+        increment(&self.inner().strong);
+        PackedRc { ptr: self.ptr, _marker: PhantomData }
+    }
+}
+
+impl<C: ?Sized> Drop for PackedRc<C> {
+    fn drop(&mut self) {
+        let strong = &self.inner().strong;
+        strong.set(strong.get() - 1);
+        if strong.get() == 0 {
+            unsafe {
+                ManuallyDrop::drop(&mut (*self.ptr.as_ptr()).value);
+                PackedRc::release(self.ptr);
+            }
+        }
+    }
+}
+
+impl<C: ?Sized> Deref for PackedRc<C> {
+    type Target = C;
+
+    fn deref(&self) -> &C {
+        &self.inner().value
+    }
+}
+
+impl<C: ?Sized + fmt::Debug> fmt::Debug for PackedRc<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+/// Non-owning handle to a [`PackedRc`], requires the `weak` feature.
+#[cfg(feature = "weak")]
+pub struct PackedWeak<C: ?Sized> {
+    // `None` for handles created by `WeakShared::new`.
+    ptr: Option<NonNull<PackedBox<C>>>,
+    _marker: PhantomData<PackedBox<C>>,
+}
+
+#[cfg(feature = "weak")]
+impl<C: ?Sized> PackedWeak<C> {
+    fn inner(&self) -> Option<&PackedBox<C>> {
+        self.ptr.map(|ptr| unsafe { &*ptr.as_ptr() })
+    }
+
+    pub fn upgrade(&self) -> Option<PackedRc<C>> {
+        let inner = self.inner()?;
+        if inner.strong.get() == 0 {
+            return None;
+        }
+        increment(&inner.strong);
+        Some(PackedRc { ptr: self.ptr?, _marker: PhantomData })
+    }
+}
+
+#[cfg(feature = "weak")]
+impl<C: ?Sized> Clone for PackedWeak<C> {
+    fn clone(&self) -> Self {
+        if let Some(inner) = self.inner() {
+            increment(&inner.weak);
+        }
+        PackedWeak { ptr: self.ptr, _marker: PhantomData }
+    }
+}
+
+#[cfg(feature = "weak")]
+impl<C: ?Sized> Drop for PackedWeak<C> {
+    fn drop(&mut self) {
+        if let Some(ptr) = self.ptr {
+            unsafe { PackedRc::release(ptr) };
+        }
+    }
+}
+
+/// `Shared` with a `Packed` allocation, e.g. `PackedShared::new_in(1)`.
+pub type PackedShared<T> = Shared<T, Packed>;
+
+/// `PackedRc<RefCell<T>>`: strong count, borrow flag and value.
+pub enum Packed {}
+
+/// `PackedRc<Cell<T>>` for `Copy` data: strong count and value, borrows
+/// copy the value like `RcCell`.
+pub enum PackedCell {}
+
+macro_rules! packed_backend {
+    ($cell:ident) => {
+        fn new(value: T) -> Self::Data where T: Sized {
+            PackedRc::new($cell::new(value))
+        }
+
+        fn strong_count(data: &Self::Data) -> usize {
+            PackedRc::strong_count(data)
+        }
+
+        fn addr(data: &Self::Data) -> *const u8 {
+            data.ptr.as_ptr() as *const u8
+        }
+
+        fn try_unwrap(data: Self::Data) -> Result<T, Self::Data> where T: Sized {
+            PackedRc::try_unwrap(data).map($cell::into_inner)
+        }
+
+        fn get_mut(data: &mut Self::Data) -> Option<&mut T> {
+            PackedRc::get_mut(data).map($cell::get_mut)
+        }
+    };
+}
+
+#[cfg(feature = "weak")]
+macro_rules! packed_weak_backend {
+    ($cell:ident) => {
+        type Weak = PackedWeak<$cell<T>>;
+
+        fn dangling() -> Self::Weak where T: Sized {
+            PackedWeak { ptr: None, _marker: PhantomData }
+        }
+
+        fn downgrade(data: &Self::Data) -> Self::Weak {
+            increment(&data.inner().weak);
+            PackedWeak { ptr: Some(data.ptr), _marker: PhantomData }
+        }
+
+        fn upgrade(weak: &Self::Weak) -> Option<Self::Data> {
+            weak.upgrade()
+        }
+
+        fn weak_count(data: &Self::Data) -> usize {
+            data.inner().weak.get() - 1
+        }
+
+        fn weak_strong_count(weak: &Self::Weak) -> usize {
+            weak.inner().map_or(0, |inner| inner.strong.get())
+        }
+
+        fn weak_weak_count(weak: &Self::Weak) -> usize {
+            match weak.inner() {
+                Some(inner) if inner.strong.get() > 0 => inner.weak.get() - 1,
+                Some(inner) => inner.weak.get(),
+                None => 0,
+            }
+        }
+
+        fn weak_ptr_eq(a: &Self::Weak, b: &Self::Weak) -> bool {
+            a.ptr == b.ptr
+        }
+    };
+}
+
+impl<T: ?Sized> Backend<T> for Packed {
+    type Data = PackedRc<RefCell<T>>;
+    type Ref<'a> = Ref<'a, T> where T: 'a;
+    type RefMut<'a> = RefMut<'a, T> where T: 'a;
+
+    packed_backend!(RefCell);
+
+    fn try_borrow(data: &Self::Data) -> Option<Self::Ref<'_>> {
+        data.try_borrow().ok()
+    }
+
+    fn try_borrow_mut(data: &Self::Data) -> Option<Self::RefMut<'_>> {
+        data.try_borrow_mut().ok()
+    }
+}
+
+// Same checks as `RcRefCell`.
+unsafe impl<T: ?Sized> RawBackend<T> for Packed {
+    fn as_ptr(data: &Self::Data) -> *mut T {
+        data.as_ptr()
+    }
+
+    #[cfg(feature = "checked")]
+    fn on_deref(data: &Self::Data) -> bool {
+        unsafe { data.try_borrow_unguarded() }.is_ok()
+    }
+
+    #[cfg(feature = "checked")]
+    fn on_deref_mut(data: &Self::Data) -> bool {
+        data.try_borrow_mut().is_ok()
+    }
+}
+
+impl<T: Copy> Backend<T> for PackedCell {
+    type Data = PackedRc<Cell<T>>;
+    type Ref<'a> = CellRef<T> where T: 'a;
+    type RefMut<'a> = CellRefMut<'a, T> where T: 'a;
+
+    packed_backend!(Cell);
+
+    fn try_borrow(data: &Self::Data) -> Option<Self::Ref<'_>> {
+        Some(CellRef { value: data.get() })
+    }
+
+    fn try_borrow_mut(data: &Self::Data) -> Option<Self::RefMut<'_>> {
+        Some(CellRefMut { cell: data, value: data.get() })
+    }
+}
+
+unsafe impl<T: Copy> RawBackend<T> for PackedCell {
+    fn as_ptr(data: &Self::Data) -> *mut T {
+        data.as_ptr()
+    }
+}
+
+macro_rules! packed_pointer_backend {
+    ($cell:ident) => {
+        type Raw = $cell<T>;
+
+        fn into_raw(data: Self::Data) -> *const Self::Raw {
+            PackedRc::into_raw(data)
+        }
+
+        unsafe fn from_raw(ptr: *const Self::Raw) -> Self::Data {
+            PackedRc::from_raw(ptr)
+        }
+    };
+}
+
+impl<T> PointerBackend<T> for Packed {
+    packed_pointer_backend!(RefCell);
+}
+
+impl<T: Copy> PointerBackend<T> for PackedCell {
+    packed_pointer_backend!(Cell);
+}
+
+#[cfg(feature = "weak")]
+impl<T: ?Sized> WeakBackend<T> for Packed {
+    packed_weak_backend!(RefCell);
+}
+
+#[cfg(feature = "weak")]
+impl<T: Copy> WeakBackend<T> for PackedCell {
+    packed_weak_backend!(Cell);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::packed::{PackedBox, PackedCell, PackedShared};
+    use crate::Shared;
+    use std::cell::{Cell, RefCell};
+    use std::mem::size_of;
+    use std::rc::Rc;
+
+    #[test]
+    fn layout() {
+        #[cfg(feature = "weak")]
+        let header = 2;
+        #[cfg(not(feature = "weak"))]
+        let header = 1;
+
+        assert_eq!(size_of::<PackedBox<RefCell<usize>>>(), (header + 2) * size_of::<usize>());
+        assert_eq!(size_of::<PackedBox<Cell<usize>>>(), (header + 1) * size_of::<usize>());
+    }
+
+    #[test]
+    fn counts() {
+        let item = Rc::new(());
+        let a = PackedShared::new_in(item.clone());
+        let b = a.clone();
+
+        assert_eq!(b.use_count(), 2);
+        assert_eq!(Rc::strong_count(&item), 2);
+        let a = Shared::try_unwrap(a).unwrap_err();
+        drop(b);
+        assert_eq!(Shared::into_inner(a), Some(item.clone()));
+        assert_eq!(Rc::strong_count(&item), 1);
+    }
+
+    #[test]
+    fn borrows() {
+        let mut a = PackedShared::new_in(vec![1]);
+        let b = a.clone();
+
+        a.push(2);
+        b.borrow_mut().push(3);
+        assert_eq!(*a.borrow(), [1, 2, 3]);
+        assert!(Shared::get_mut(&mut a).is_none());
+        drop(b);
+        Shared::get_mut(&mut a).unwrap().clear();
+        assert!(a.is_empty());
+
+        let mut c = Shared::<_, PackedCell>::new_in(1);
+        let d = c.clone();
+        *c += 1;
+        *d.borrow_mut() += 1;
+        assert_eq!(*c, 3);
+    }
+
+    #[test]
+    #[cfg(feature = "weak")]
+    fn weak() {
+        use crate::WeakShared;
+
+        let a = PackedShared::new_in(String::from("a"));
+        let weak = a.downgrade();
+
+        assert_eq!(a.weak_count(), 1);
+        assert_eq!(weak.upgrade(), Some(a.clone()));
+        assert_eq!(Shared::try_unwrap(a).unwrap(), "a");
+        assert_eq!(weak.upgrade(), None);
+        assert_eq!(weak.weak_count(), 1);
+        assert!(WeakShared::<u8, PackedCell>::new().upgrade().is_none());
+    }
+}
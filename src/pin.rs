@@ -0,0 +1,187 @@
+use alloc::boxed::Box;
+use core::fmt;
+use core::ops::Deref;
+use core::pin::Pin;
+
+use crate::backend::Backend;
+use crate::{RcRefCell, Shared, SharedRef, SharedRefMut};
+
+impl<T> Shared<T> {
+    /// Pins the value in its allocation, which never moves while a handle is
+    /// alive. A `Pin<Shared<T>>` hands out `Pin<&mut T>` through `as_mut`,
+    /// and `&mut T` only if `T: Unpin`:
+    ///
+    /// ```compile_fail
+    /// let mut pinned = shared::Shared::pin(core::marker::PhantomPinned);
+    /// let _: &mut core::marker::PhantomPinned = &mut pinned;
+    /// ```
+    ///
+    /// Clones of a pinned handle are pinned too, and there is no way back to
+    /// an unpinned one that could `try_unwrap` or `make_mut` the value.
+    pub fn pin(value: T) -> Pin<Self> {
+        unsafe { Pin::new_unchecked(Shared::new(value)) }
+    }
+}
+
+impl<T: ?Sized, B: Backend<T>> Shared<T, B> {
+    fn get_pinned(this: &Pin<Self>) -> &Self {
+        // `Pin` is `repr(transparent)`. The handle is only used for guards,
+        // which cannot move the value either.
+        unsafe { &*(this as *const Pin<Self> as *const Self) }
+    }
+
+    /// Immutably borrows a pinned value, see [`Shared::borrow`].
+    #[track_caller]
+    pub fn borrow_pin(this: &Pin<Self>) -> Pin<SharedRef<'_, T, B>> {
+        unsafe { Pin::new_unchecked(Shared::get_pinned(this).borrow()) }
+    }
+
+    /// Mutably borrows a pinned value, `as_mut` on the guard gives the
+    /// `Pin<&mut T>` needed to poll a future.
+    #[track_caller]
+    pub fn borrow_mut_pin(this: &Pin<Self>) -> Pin<SharedRefMut<'_, T, B>> {
+        unsafe { Pin::new_unchecked(Shared::get_pinned(this).borrow_mut()) }
+    }
+}
+
+type Proj<'b, T, U> = Box<dyn Fn(Pin<&T>) -> Pin<&U> + 'b>;
+type ProjMut<'b, T, U> = Box<dyn Fn(Pin<&mut T>) -> Pin<&mut U> + 'b>;
+
+/// Pinned borrow of a part of a `Shared` value, created by
+/// [`SharedRef::map_pin`]. The projection is re-applied on every access.
+pub struct PinnedRef<'b, T: ?Sized + 'b, U: ?Sized, B: Backend<T> + 'b = RcRefCell> {
+    guard: Pin<SharedRef<'b, T, B>>,
+    proj: Proj<'b, T, U>,
+}
+
+/// Pinned mutable borrow of a part of a `Shared` value, created by
+/// [`SharedRefMut::map_pin`]. The projection is re-applied on every access.
+pub struct PinnedRefMut<'b, T: ?Sized + 'b, U: ?Sized, B: Backend<T> + 'b = RcRefCell> {
+    guard: Pin<SharedRefMut<'b, T, B>>,
+    proj: ProjMut<'b, T, U>,
+}
+
+impl<'b, T: ?Sized, B: Backend<T>> SharedRef<'b, T, B> {
+    /// Projects a pinned guard to a part of the value, keeping it borrowed.
+    /// `f` only gets the value pinned, so the part stays pinned as well.
+    pub fn map_pin<U: ?Sized, F>(this: Pin<Self>, f: F) -> PinnedRef<'b, T, U, B>
+    where
+        F: Fn(Pin<&T>) -> Pin<&U> + 'b,
+    {
+        PinnedRef { guard: this, proj: Box::new(f) }
+    }
+}
+
+impl<'b, T: ?Sized, B: Backend<T>> SharedRefMut<'b, T, B> {
+    /// Projects a pinned guard to a field, e.g. a future to poll, with
+    /// `pin-project` or `Pin::map_unchecked_mut`. `f` only gets the value
+    /// pinned, so the part stays pinned as well.
+    pub fn map_pin<U: ?Sized, F>(this: Pin<Self>, f: F) -> PinnedRefMut<'b, T, U, B>
+    where
+        F: Fn(Pin<&mut T>) -> Pin<&mut U> + 'b,
+    {
+        PinnedRefMut { guard: this, proj: Box::new(f) }
+    }
+}
+
+impl<T: ?Sized, U: ?Sized, B: Backend<T>> PinnedRef<'_, T, U, B> {
+    pub fn as_ref(&self) -> Pin<&U> {
+        (self.proj)(self.guard.as_ref())
+    }
+}
+
+impl<T: ?Sized, U: ?Sized, B: Backend<T>> Deref for PinnedRef<'_, T, U, B> {
+    type Target = U;
+
+    fn deref(&self) -> &U {
+        self.as_ref().get_ref()
+    }
+}
+
+impl<T: ?Sized, U: ?Sized, B: Backend<T>> PinnedRefMut<'_, T, U, B> {
+    pub fn as_mut(&mut self) -> Pin<&mut U> {
+        (self.proj)(self.guard.as_mut())
+    }
+}
+
+impl<T: ?Sized, U: ?Sized + fmt::Debug, B: Backend<T>> fmt::Debug for PinnedRef<'_, T, U, B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+impl<T: ?Sized, U: ?Sized, B: Backend<T>> fmt::Debug for PinnedRefMut<'_, T, U, B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("PinnedRefMut { .. }")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Shared, SharedRef, SharedRefMut};
+    use std::future::Future;
+    use std::marker::PhantomPinned;
+    use std::pin::Pin;
+    use std::ptr;
+    use std::task::{Context, Poll, Waker};
+
+    // Remembers its own address on the first poll.
+    struct SelfRef {
+        this: *const SelfRef,
+        _pinned: PhantomPinned,
+    }
+
+    impl Future for SelfRef {
+        type Output = bool;
+
+        fn poll(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<bool> {
+            let addr = &*self as *const SelfRef;
+            let this = unsafe { &mut self.get_unchecked_mut().this };
+            if this.is_null() {
+                *this = addr;
+                Poll::Pending
+            } else {
+                Poll::Ready(ptr::eq(*this, addr))
+            }
+        }
+    }
+
+    #[test]
+    fn pinned_future() {
+        let future = Shared::pin(SelfRef { this: ptr::null(), _pinned: PhantomPinned });
+        let other = future.clone();
+        let mut cx = Context::from_waker(Waker::noop());
+
+        assert_eq!(Shared::borrow_mut_pin(&future).as_mut().poll(&mut cx), Poll::Pending);
+        assert!(!Shared::borrow_pin(&other).this.is_null());
+        assert_eq!(Shared::borrow_mut_pin(&other).as_mut().poll(&mut cx), Poll::Ready(true));
+    }
+
+    struct Task {
+        polls: u32,
+        future: SelfRef,
+    }
+
+    #[test]
+    fn pinned_fields() {
+        let task = Shared::pin(Task { polls: 0, future: SelfRef { this: ptr::null(), _pinned: PhantomPinned } });
+        let mut cx = Context::from_waker(Waker::noop());
+
+        for expected in [Poll::Pending, Poll::Ready(true)] {
+            let mut guard = Shared::borrow_mut_pin(&task);
+            unsafe { guard.as_mut().get_unchecked_mut().polls += 1 };
+            let mut future = SharedRefMut::map_pin(guard, |task| unsafe { task.map_unchecked_mut(|task| &mut task.future) });
+            assert_eq!(future.as_mut().poll(&mut cx), expected);
+        }
+        let polls = SharedRef::map_pin(Shared::borrow_pin(&task), |task| Pin::new(&task.get_ref().polls));
+        assert_eq!(*polls, 2);
+    }
+
+    #[test]
+    fn unpin() {
+        let mut pinned = Shared::pin(1);
+        *pinned += 1;
+        pinned.set(3);
+        assert_eq!(*Shared::borrow_pin(&pinned), 3);
+    }
+}
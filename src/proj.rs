@@ -0,0 +1,252 @@
+use alloc::rc::Rc;
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+
+use crate::backend::RawBackend;
+use crate::{RcRefCell, Shared};
+
+/// Handle to a part of a `Shared` value, created by [`project!`](crate::project)
+/// or [`Shared::map`].
+///
+/// The projection keeps the whole allocation alive and counts as a strong
+/// handle, but only ever hands out the projected `U`. It is re-applied on
+/// every access, so it stays valid while the value is mutated through other
+/// handles.
+pub struct SharedProj<T: ?Sized, U: ?Sized, B: RawBackend<T> = RcRefCell> {
+    shared: Shared<T, B>,
+    proj: Rc<dyn Projection<T, U>>,
+}
+
+// Both directions of a projection, kept together so that a `SharedProj`
+// needs a single allocation however often it is re-projected.
+trait Projection<T: ?Sized, U: ?Sized> {
+    fn get<'a>(&self, value: &'a T) -> &'a U;
+    fn get_mut<'a>(&self, value: &'a mut T) -> &'a mut U;
+}
+
+struct Field<F, G>(F, G);
+
+impl<T: ?Sized, U: ?Sized, F, G> Projection<T, U> for Field<F, G>
+where
+    F: Fn(&T) -> &U,
+    G: Fn(&mut T) -> &mut U,
+{
+    fn get<'a>(&self, value: &'a T) -> &'a U {
+        (self.0)(value)
+    }
+
+    fn get_mut<'a>(&self, value: &'a mut T) -> &'a mut U {
+        (self.1)(value)
+    }
+}
+
+struct Then<T: ?Sized, U: ?Sized, P>(Rc<dyn Projection<T, U>>, P);
+
+impl<T: ?Sized, U: ?Sized + 'static, V: ?Sized, P: Projection<U, V>> Projection<T, V> for Then<T, U, P> {
+    fn get<'a>(&self, value: &'a T) -> &'a V {
+        self.1.get(self.0.get(value))
+    }
+
+    fn get_mut<'a>(&self, value: &'a mut T) -> &'a mut V {
+        self.1.get_mut(self.0.get_mut(value))
+    }
+}
+
+/// Projects a `Shared` or a `SharedProj` to a field path, e.g.
+/// `project!(scene => camera.zoom)` or `project!(names => [0])`.
+///
+/// The path is written once and expands to the `&` and `&mut` projections
+/// that [`Shared::map`] takes.
+///
+/// ```
+/// use shared::{project, Shared};
+///
+/// struct Camera { zoom: f32 }
+/// struct Scene { camera: Camera, names: Vec<String> }
+///
+/// let scene = Shared::new(Scene { camera: Camera { zoom: 1.0 }, names: vec![] });
+/// let mut camera = project!(scene => camera);
+/// let zoom = project!(camera => zoom);
+/// camera.zoom = 2.0;
+/// assert_eq!(*zoom, 2.0);
+/// ```
+#[macro_export]
+macro_rules! project {
+    ($shared:expr => [$($index:tt)*]) => {
+        $crate::__private::Project::project(&$shared, |value| &value[$($index)*], |value| &mut value[$($index)*])
+    };
+    ($shared:expr => $($path:tt)+) => {
+        $crate::__private::Project::project(&$shared, |value| &value.$($path)+, |value| &mut value.$($path)+)
+    };
+}
+
+// Lets `project!` take both handle types.
+#[doc(hidden)]
+pub trait Project<T: ?Sized, U: ?Sized, B: RawBackend<T>> {
+    fn project<V: ?Sized, F, G>(&self, f: F, f_mut: G) -> SharedProj<T, V, B>
+    where
+        F: Fn(&U) -> &V + 'static,
+        G: Fn(&mut U) -> &mut V + 'static;
+}
+
+impl<T: ?Sized, B: RawBackend<T>> Project<T, T, B> for Shared<T, B> {
+    fn project<V: ?Sized, F, G>(&self, f: F, f_mut: G) -> SharedProj<T, V, B>
+    where
+        F: Fn(&T) -> &V + 'static,
+        G: Fn(&mut T) -> &mut V + 'static,
+    {
+        Shared::map(self, f, f_mut)
+    }
+}
+
+impl<T: ?Sized + 'static, U: ?Sized + 'static, B: RawBackend<T>> Project<T, U, B> for SharedProj<T, U, B> {
+    fn project<V: ?Sized, F, G>(&self, f: F, f_mut: G) -> SharedProj<T, V, B>
+    where
+        F: Fn(&U) -> &V + 'static,
+        G: Fn(&mut U) -> &mut V + 'static,
+    {
+        SharedProj::map(self, f, f_mut)
+    }
+}
+
+impl<T: ?Sized, B: RawBackend<T>> Shared<T, B> {
+    /// Projects the value to one of its parts, e.g.
+    /// `Shared::map(&scene, |scene| &scene.camera, |scene| &mut scene.camera)`;
+    /// [`project!`](crate::project) writes both closures from one field path.
+    ///
+    /// `f` serves `Deref` and `f_mut` serves `DerefMut`, both should pick the
+    /// same part. Reads never see a `&mut T`, so they cannot invalidate
+    /// references handed out earlier.
+    pub fn map<U: ?Sized, F, G>(this: &Self, f: F, f_mut: G) -> SharedProj<T, U, B>
+    where
+        F: Fn(&T) -> &U + 'static,
+        G: Fn(&mut T) -> &mut U + 'static,
+    {
+        SharedProj { shared: this.clone(), proj: Rc::new(Field(f, f_mut)) }
+    }
+}
+
+impl<T: ?Sized + 'static, U: ?Sized + 'static, B: RawBackend<T>> SharedProj<T, U, B> {
+    /// Projects further, the result still owns the original allocation.
+    pub fn map<V: ?Sized, F, G>(this: &Self, f: F, f_mut: G) -> SharedProj<T, V, B>
+    where
+        F: Fn(&U) -> &V + 'static,
+        G: Fn(&mut U) -> &mut V + 'static,
+    {
+        SharedProj { shared: this.shared.clone(), proj: Rc::new(Then(this.proj.clone(), Field(f, f_mut))) }
+    }
+}
+
+impl<T: ?Sized, U: ?Sized, B: RawBackend<T>> SharedProj<T, U, B> {
+    pub fn use_count(&self) -> usize {
+        self.shared.use_count()
+    }
+}
+
+impl<T: ?Sized, U: ?Sized, B: RawBackend<T>> Clone for SharedProj<T, U, B> {
+    fn clone(&self) -> Self {
+        SharedProj { shared: self.shared.clone(), proj: self.proj.clone() }
+    }
+}
+
+impl<T: ?Sized, U: ?Sized, B: RawBackend<T>> Deref for SharedProj<T, U, B> {
+    type Target = U;
+
+    #[cfg_attr(feature = "checked", track_caller)]
+    fn deref(&self) -> &U {
+        if !B::on_deref(&self.shared.data) {
+            self.shared.conflict("cannot dereference `SharedProj`: value is already mutably borrowed");
+        }
+        self.proj.get(unsafe { &*B::as_ptr(&self.shared.data) })
+    }
+}
+
+impl<T: ?Sized, U: ?Sized, B: RawBackend<T>> DerefMut for SharedProj<T, U, B> {
+    #[cfg_attr(feature = "checked", track_caller)]
+    fn deref_mut(&mut self) -> &mut U {
+        if !B::on_deref_mut(&self.shared.data) {
+            self.shared.conflict("cannot mutably dereference `SharedProj`: value is already borrowed");
+        }
+        self.proj.get_mut(unsafe { &mut *B::as_ptr(&self.shared.data) })
+    }
+}
+
+impl<T: ?Sized, U: ?Sized, B: RawBackend<T>> fmt::Debug for SharedProj<T, U, B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SharedProj { .. }")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Shared, SharedProj};
+
+    #[derive(Default)]
+    struct Camera {
+        zoom: f32,
+        target: [f32; 2],
+    }
+
+    #[derive(Default)]
+    struct Scene {
+        camera: Camera,
+        names: Vec<String>,
+    }
+
+    #[test]
+    fn map() {
+        let scene = Shared::new(Scene::default());
+        let mut camera = project!(scene => camera);
+        let mut target = SharedProj::map(
+            &camera,
+            |camera| &camera.target[..],
+            |camera| &mut camera.target[..],
+        );
+
+        camera.zoom = 2.0;
+        target[1] = 1.0;
+        assert_eq!(scene.camera.zoom, 2.0);
+        assert_eq!(scene.camera.target, [0.0, 1.0]);
+        assert_eq!(scene.use_count(), 3);
+
+        drop(scene);
+        assert_eq!(camera.use_count(), 2);
+        assert_eq!(target.len(), 2);
+    }
+
+    #[test]
+    fn follows_mutation() {
+        let mut scene = Shared::new(Scene::default());
+        let first = project!(scene => names[0]);
+
+        scene.names.push("a".to_owned());
+        assert_eq!(*first, "a");
+        scene.names = vec!["b".to_owned()];
+        assert_eq!(*first, "b");
+    }
+
+    #[test]
+    fn live_derefs() {
+        let scene = Shared::new(Scene::default());
+        let names = project!(scene => names);
+        let mut first = project!(names => [0]);
+
+        scene.borrow_mut().names.push(String::new());
+        first.push('a');
+        let a = &*first;
+        let b = &*first;
+        assert_eq!((a.as_str(), names.len()), ("a", 1));
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    #[cfg(feature = "checked")]
+    #[should_panic(expected = "already mutably borrowed")]
+    fn checked_deref() {
+        let scene = Shared::new(Scene::default());
+        let camera = project!(scene => camera);
+
+        let _guard = scene.borrow_mut();
+        let _ = camera.zoom;
+    }
+}
@@ -0,0 +1,482 @@
+//! Signals, computed values and effects built on [`ObservedShared`].
+//!
+//! Reading a [`Signal`] or [`Computed`] while a `Computed` or [`Effect`] is
+//! evaluated records it as a dependency. Writing a signal marks its direct
+//! dependents dirty and everything further down as "check", then reruns the
+//! effects; each of them first brings its dependencies up to date, in
+//! dependency order, so nothing ever sees a mix of old and new values.
+//! A `Computed` whose new value equals the old one stops the propagation.
+//!
+//! Effects run from the notification of the written signal, so they write
+//! signals with `set`/`update` rather than `borrow_mut`.
+//!
+//! Like change notification, the graph is per thread.
+
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::rc::{Rc, Weak};
+
+use crate::observe::{self, ObservedShared, OnDrop, Subscription};
+use crate::{Shared, SharedRef, SharedRefMut};
+
+#[derive(Clone, Copy, PartialEq, PartialOrd)]
+enum State {
+    Clean,
+    Check,
+    Dirty,
+}
+
+type Compute = Box<dyn FnMut() -> bool>;
+
+struct Node {
+    state: Cell<State>,
+    // Set while `compute` runs, reading the node then is a cycle.
+    computing: Cell<bool>,
+    effect: bool,
+    // Returns whether the value changed, `None` for signals.
+    compute: RefCell<Option<Compute>>,
+    sources: RefCell<Vec<Rc<Node>>>,
+    observers: RefCell<Vec<Weak<Node>>>,
+}
+
+thread_local! {
+    static TRACKING: RefCell<Option<Vec<Rc<Node>>>> = const { RefCell::new(None) };
+    static EFFECTS: RefCell<Vec<Weak<Node>>> = const { RefCell::new(Vec::new()) };
+    static RUNNING: Cell<bool> = const { Cell::new(false) };
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+impl Node {
+    fn new(state: State, effect: bool, compute: Option<Compute>) -> Rc<Node> {
+        Rc::new(Node {
+            state: Cell::new(state),
+            computing: Cell::new(false),
+            effect,
+            compute: RefCell::new(compute),
+            sources: RefCell::new(Vec::new()),
+            observers: RefCell::new(Vec::new()),
+        })
+    }
+
+    fn observers(&self) -> Vec<Rc<Node>> {
+        let mut observers = self.observers.borrow_mut();
+        observers.retain(|observer| observer.strong_count() > 0);
+        observers.iter().filter_map(Weak::upgrade).collect()
+    }
+
+    /// Records `node` as a dependency of the evaluation in progress.
+    fn track(node: &Rc<Node>) {
+        TRACKING.with(|tracking| {
+            if let Some(sources) = &mut *tracking.borrow_mut() {
+                if !sources.iter().any(|source| Rc::ptr_eq(source, node)) {
+                    sources.push(node.clone());
+                }
+            }
+        });
+    }
+
+    fn stale(node: &Rc<Node>, state: State) {
+        if node.state.get() >= state {
+            return;
+        }
+        if node.state.get() == State::Clean && node.effect {
+            EFFECTS.with(|effects| effects.borrow_mut().push(Rc::downgrade(node)));
+        }
+        node.state.set(state);
+        for observer in node.observers() {
+            Node::stale(&observer, State::Check);
+        }
+    }
+
+    fn update(node: &Rc<Node>) {
+        if node.computing.get() {
+            panic!("cycle in the reactive graph: a `Computed` depends on its own value");
+        }
+        if node.state.get() == State::Check {
+            let sources = node.sources.borrow().clone();
+            for source in sources {
+                Node::update(&source);
+                if node.state.get() == State::Dirty {
+                    break;
+                }
+            }
+        }
+        if node.state.get() == State::Dirty {
+            Node::recompute(node);
+        }
+        node.state.set(State::Clean);
+    }
+
+    fn recompute(node: &Rc<Node>) {
+        // Puts the outer evaluation back even if `compute` panics.
+        let outer = TRACKING.with(|tracking| tracking.replace(Some(Vec::new())));
+        node.computing.set(true);
+        let outer = OnDrop::new(|| {
+            node.computing.set(false);
+            TRACKING.with(|tracking| tracking.replace(outer));
+        });
+        let changed = node.compute.borrow_mut().as_mut().is_some_and(|compute| compute());
+        let sources = TRACKING.with(|tracking| tracking.take()).unwrap_or_default();
+        drop(outer);
+
+        for source in node.sources.replace(sources.clone()) {
+            source.observers.borrow_mut().retain(|observer| observer.as_ptr() != Rc::as_ptr(node));
+        }
+        for source in &sources {
+            source.observers.borrow_mut().push(Rc::downgrade(node));
+        }
+        if changed {
+            for observer in node.observers() {
+                observer.state.set(State::Dirty);
+            }
+        }
+    }
+
+    fn changed(node: &Rc<Node>) {
+        for observer in node.observers() {
+            Node::stale(&observer, State::Dirty);
+        }
+        if DEPTH.with(Cell::get) == 0 {
+            run_effects();
+        }
+    }
+}
+
+fn run_effects() {
+    if RUNNING.with(|running| running.replace(true)) {
+        return;
+    }
+    let running = RefCell::new(Vec::new());
+    // Clears `RUNNING` when done. If an effect panics, the ones not brought
+    // up to date yet (the panicking one included) are queued again, to run
+    // with the next change.
+    let _running = OnDrop::new(|| {
+        let mut pending: Vec<Weak<Node>> = running.take();
+        pending.retain(|effect| {
+            effect.upgrade().is_some_and(|effect| effect.state.get() != State::Clean)
+        });
+        EFFECTS.with(|effects| effects.borrow_mut().extend(pending));
+        RUNNING.with(|running| running.set(false));
+    });
+    loop {
+        let effects = EFFECTS.with(|effects| effects.take());
+        if effects.is_empty() {
+            break;
+        }
+        running.replace(effects.clone());
+        for effect in effects.iter().filter_map(Weak::upgrade) {
+            Node::update(&effect);
+        }
+    }
+}
+
+/// Runs `f` as an [`observe::batch`], rerunning effects once at the end.
+pub fn batch<R>(f: impl FnOnce() -> R) -> R {
+    DEPTH.with(|depth| depth.set(depth.get() + 1));
+    let depth = OnDrop::new(|| DEPTH.with(|depth| depth.set(depth.get() - 1)));
+    let result = observe::batch(f);
+    drop(depth);
+    if DEPTH.with(Cell::get) == 0 {
+        run_effects();
+    }
+    result
+}
+
+/// Observed value whose readers are tracked.
+pub struct Signal<T: 'static> {
+    shared: ObservedShared<T>,
+    node: Rc<Node>,
+    _subscription: Rc<Subscription>,
+}
+
+impl<T: 'static> Signal<T> {
+    pub fn new(value: T) -> Self {
+        let shared = ObservedShared::new_in(value);
+        let node = Node::new(State::Clean, false, None);
+        let subscription = shared.subscribe({
+            let node = Rc::downgrade(&node);
+            move |_| {
+                if let Some(node) = node.upgrade() {
+                    Node::changed(&node);
+                }
+            }
+        });
+        Signal { shared, node, _subscription: Rc::new(subscription) }
+    }
+
+    #[track_caller]
+    pub fn borrow(&self) -> SharedRef<'_, T, observe::Observed> {
+        Node::track(&self.node);
+        self.shared.borrow()
+    }
+
+    /// Mutable guard, dependents are updated when it is dropped.
+    #[track_caller]
+    pub fn borrow_mut(&self) -> SharedRefMut<'_, T, observe::Observed> {
+        self.shared.borrow_mut()
+    }
+
+    pub fn with<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        f(&self.borrow())
+    }
+
+    pub fn get(&self) -> T
+    where
+        T: Clone,
+    {
+        self.borrow().clone()
+    }
+
+    pub fn set(&self, value: T) {
+        self.update(|current| *current = value);
+    }
+
+    /// See [`ObservedShared::update`](crate::Shared::update).
+    pub fn update(&self, f: impl FnOnce(&mut T) + 'static) {
+        self.shared.update(f);
+    }
+}
+
+impl<T: 'static> Clone for Signal<T> {
+    fn clone(&self) -> Self {
+        Signal {
+            shared: self.shared.clone(),
+            node: self.node.clone(),
+            _subscription: self._subscription.clone(),
+        }
+    }
+}
+
+impl<T: fmt::Debug + 'static> fmt::Debug for Signal<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Signal {{ data: {:?} }}", self.shared.data)
+    }
+}
+
+/// Value derived from signals and other computed values, recomputed lazily
+/// when read after one of them changed.
+pub struct Computed<T> {
+    value: Shared<Option<T>>,
+    node: Rc<Node>,
+}
+
+impl<T: PartialEq + 'static> Computed<T> {
+    pub fn new(f: impl Fn() -> T + 'static) -> Self {
+        let value = Shared::new(None);
+        let compute = {
+            let value = value.clone();
+            move || {
+                let new = f();
+                let mut value = value.borrow_mut();
+                if value.as_ref() == Some(&new) {
+                    return false;
+                }
+                *value = Some(new);
+                true
+            }
+        };
+        let node = Node::new(State::Dirty, false, Some(Box::new(compute)));
+        Computed { value, node }
+    }
+}
+
+impl<T> Computed<T> {
+    pub fn with<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        Node::track(&self.node);
+        Node::update(&self.node);
+        f(self.value.borrow().as_ref().expect("`Computed` is evaluated"))
+    }
+
+    pub fn get(&self) -> T
+    where
+        T: Clone,
+    {
+        self.with(T::clone)
+    }
+}
+
+impl<T> Clone for Computed<T> {
+    fn clone(&self) -> Self {
+        Computed { value: self.value.clone(), node: self.node.clone() }
+    }
+}
+
+impl<T> fmt::Debug for Computed<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Computed { .. }")
+    }
+}
+
+/// Side effect rerun whenever a value it read changes, until dropped.
+#[must_use = "dropping an `Effect` stops it"]
+pub struct Effect {
+    _node: Rc<Node>,
+}
+
+impl Effect {
+    pub fn new(mut f: impl FnMut() + 'static) -> Self {
+        let compute = move || {
+            f();
+            false
+        };
+        let node = Node::new(State::Dirty, true, Some(Box::new(compute)));
+        Node::update(&node);
+        Effect { _node: node }
+    }
+}
+
+impl fmt::Debug for Effect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Effect { .. }")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::reactive::{batch, Computed, Effect, Signal};
+    use std::cell::{Cell, RefCell};
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    use std::rc::Rc;
+
+    #[test]
+    fn diamond() {
+        let a = Signal::new(1);
+        let runs = Rc::new(Cell::new(0));
+        let b = Computed::new({
+            let a = a.clone();
+            move || a.get() * 2
+        });
+        let c = Computed::new({
+            let a = a.clone();
+            move || a.get() + 1
+        });
+        let d = Computed::new({
+            let (b, c, runs) = (b.clone(), c.clone(), runs.clone());
+            move || {
+                runs.set(runs.get() + 1);
+                b.get() + c.get()
+            }
+        });
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let _effect = Effect::new({
+            let (d, seen) = (d.clone(), seen.clone());
+            move || seen.borrow_mut().push(d.get())
+        });
+
+        a.set(2);
+        a.set(3);
+        assert_eq!(*seen.borrow(), [4, 7, 10]);
+        assert_eq!(runs.get(), 3);
+    }
+
+    #[test]
+    fn lazy_and_cutoff() {
+        let a = Signal::new(1);
+        let runs = Rc::new(Cell::new(0));
+        let parity = Computed::new({
+            let a = a.clone();
+            move || a.get() % 2
+        });
+        let label = Computed::new({
+            let (parity, runs) = (parity.clone(), runs.clone());
+            move || {
+                runs.set(runs.get() + 1);
+                if parity.get() == 0 { "even" } else { "odd" }
+            }
+        });
+
+        a.set(2);
+        assert_eq!(runs.get(), 0);
+        assert_eq!(label.get(), "even");
+        a.set(4);
+        assert_eq!(label.get(), "even");
+        assert_eq!(runs.get(), 1);
+    }
+
+    #[test]
+    fn dynamic_dependencies_and_drop() {
+        let flag = Signal::new(true);
+        let a = Signal::new(1);
+        let b = Signal::new(2);
+        let runs = Rc::new(Cell::new(0));
+        let effect = Effect::new({
+            let (flag, a, b, runs) = (flag.clone(), a.clone(), b.clone(), runs.clone());
+            move || {
+                runs.set(runs.get() + 1);
+                let _ = if flag.get() { a.get() } else { b.get() };
+            }
+        });
+
+        b.set(3);
+        assert_eq!(runs.get(), 1);
+        flag.set(false);
+        a.set(4);
+        assert_eq!(runs.get(), 2);
+        b.set(5);
+        assert_eq!(runs.get(), 3);
+
+        drop(effect);
+        b.set(6);
+        assert_eq!(runs.get(), 3);
+    }
+
+    #[test]
+    fn batched() {
+        let a = Signal::new(1);
+        let b = Signal::new(1);
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let _effect = Effect::new({
+            let (a, b, seen) = (a.clone(), b.clone(), seen.clone());
+            move || seen.borrow_mut().push(a.get() + b.get())
+        });
+
+        batch(|| {
+            a.set(2);
+            *b.borrow_mut() = 2;
+        });
+        assert_eq!(*seen.borrow(), [2, 4]);
+    }
+
+    #[test]
+    #[should_panic(expected = "depends on its own value")]
+    fn cycle() {
+        let a = Signal::new(1);
+        let slot = Rc::new(RefCell::new(None::<Computed<i32>>));
+        let b = Computed::new({
+            let (a, slot) = (a.clone(), slot.clone());
+            move || a.get() + slot.borrow().as_ref().map_or(0, Computed::get)
+        });
+        *slot.borrow_mut() = Some(b.clone());
+        b.get();
+    }
+
+    #[test]
+    fn recovers_from_panics() {
+        let a = Signal::new(0);
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let _effect = Effect::new({
+            let (a, seen) = (a.clone(), seen.clone());
+            move || {
+                let value = a.get();
+                assert_ne!(value, 1);
+                seen.borrow_mut().push(value);
+            }
+        });
+        let checked = Computed::new({
+            let a = a.clone();
+            move || {
+                assert_ne!(a.get(), 3);
+                a.get()
+            }
+        });
+
+        assert!(catch_unwind(AssertUnwindSafe(|| a.set(1))).is_err());
+        a.set(2);
+        assert!(catch_unwind(AssertUnwindSafe(|| batch(|| panic!()))).is_err());
+        a.set(3);
+        assert!(catch_unwind(AssertUnwindSafe(|| checked.get())).is_err());
+        a.set(4);
+        assert_eq!(checked.get(), 4);
+        assert_eq!(*seen.borrow(), [0, 2, 3, 4]);
+    }
+}
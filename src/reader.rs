@@ -0,0 +1,135 @@
+use core::fmt;
+use core::ops::Deref;
+
+use crate::backend::{Backend, RawBackend};
+use crate::{BorrowError, RcRefCell, Shared, SharedRef};
+
+/// Read-only handle to a `Shared` allocation, created by [`Shared::reader`].
+///
+/// It shares the allocation and counts as a strong handle, but has no way to
+/// mutate the value or to get a `Shared` back.
+///
+/// ```compile_fail
+/// let values = shared::Shared::new(vec![1]);
+/// let mut reader = shared::Shared::reader(&values);
+/// reader.push(2);
+/// ```
+pub struct SharedReader<T: ?Sized, B: Backend<T> = RcRefCell> {
+    shared: Shared<T, B>,
+}
+
+impl<T: ?Sized, B: Backend<T>> Shared<T, B> {
+    pub fn reader(this: &Self) -> SharedReader<T, B> {
+        SharedReader { shared: this.clone() }
+    }
+}
+
+impl<T: ?Sized, B: Backend<T>> SharedReader<T, B> {
+    pub fn use_count(&self) -> usize {
+        self.shared.use_count()
+    }
+
+    #[track_caller]
+    pub fn borrow(&self) -> SharedRef<'_, T, B> {
+        self.shared.borrow()
+    }
+
+    #[track_caller]
+    pub fn try_borrow(&self) -> Result<SharedRef<'_, T, B>, BorrowError> {
+        self.shared.try_borrow()
+    }
+
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        Shared::ptr_eq(&this.shared, &other.shared)
+    }
+}
+
+impl<T: ?Sized, B: Backend<T>> Clone for SharedReader<T, B> {
+    fn clone(&self) -> Self {
+        SharedReader { shared: self.shared.clone() }
+    }
+}
+
+impl<T: ?Sized, B: RawBackend<T>> Deref for SharedReader<T, B> {
+    type Target = T;
+
+    #[cfg_attr(feature = "checked", track_caller)]
+    fn deref(&self) -> &T {
+        &self.shared
+    }
+}
+
+impl<T: ?Sized, B: RawBackend<T>> AsRef<T> for SharedReader<T, B> {
+    fn as_ref(&self) -> &T {
+        self
+    }
+}
+
+// Identity comparisons, like `Shared`.
+impl<T: ?Sized, B: Backend<T>> PartialEq for SharedReader<T, B> {
+    fn eq(&self, other: &Self) -> bool {
+        SharedReader::ptr_eq(self, other)
+    }
+}
+
+impl<T: ?Sized, B: Backend<T>> Eq for SharedReader<T, B> { }
+
+impl<T: ?Sized, B: Backend<T>> PartialEq<Shared<T, B>> for SharedReader<T, B> {
+    fn eq(&self, other: &Shared<T, B>) -> bool {
+        Shared::ptr_eq(&self.shared, other)
+    }
+}
+
+impl<T: ?Sized, B: Backend<T>> PartialEq<SharedReader<T, B>> for Shared<T, B> {
+    fn eq(&self, other: &SharedReader<T, B>) -> bool {
+        Shared::ptr_eq(self, &other.shared)
+    }
+}
+
+impl<T: ?Sized, B: Backend<T>> fmt::Debug for SharedReader<T, B>
+where
+    B::Data: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SharedReader {{ data: {:?} }}", self.shared.data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "std")]
+    use crate::sync::ArcRwLock;
+    use crate::{Shared, SharedReader};
+
+    fn total(values: &SharedReader<Vec<i32>>) -> i32 {
+        values.iter().sum()
+    }
+
+    #[test]
+    fn reader() {
+        let mut values = Shared::new(vec![1, 2]);
+        let reader = Shared::reader(&values);
+
+        values.push(3);
+        assert_eq!(total(&reader), 6);
+        assert_eq!(values.use_count(), 2);
+        assert_eq!(reader.use_count(), 2);
+        assert_eq!(values, reader);
+        assert_eq!(reader, values);
+        assert_eq!(reader, reader.clone());
+        assert!(reader != Shared::new(vec![1, 2, 3]));
+        assert_eq!(format!("{:?}", reader), "SharedReader { data: RefCell { value: [1, 2, 3] } }");
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn guards() {
+        let a = Shared::<_, ArcRwLock>::new_in(1);
+        let reader = Shared::reader(&a);
+
+        *a.borrow_mut() += 1;
+        let _x = reader.borrow();
+        assert_eq!(*reader.try_borrow().unwrap(), 2);
+        assert!(a.try_borrow_mut().is_err());
+    }
+}
@@ -0,0 +1,344 @@
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::sync::{Arc, Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard, TryLockError, Weak};
+
+use crate::backend::{pointer_backend, Backend, PointerBackend, WeakBackend};
+use crate::Shared;
+
+/// Thread-safe `Shared`, backed by `Arc` and a lock.
+///
+/// Lock backends do not implement `RawBackend`, so the value is only
+/// reachable through guards; `borrow` and `borrow_mut` block until the lock
+/// is available. Poisoning is ignored: a guard is handed out even if a
+/// previous holder panicked, the same way a `RefCell` stays usable after a
+/// panic.
+///
+/// `SyncShared` derefs to the `Shared` it wraps, which carries the borrow
+/// and weak API; `into_shared` unwraps it. `B` is one of the `Arc` backends
+/// below, `ArcRwLock` unless named with `new_in`.
+#[repr(transparent)]
+pub struct SyncShared<T: ?Sized, B: Backend<T> = ArcRwLock>(Shared<T, B>);
+
+impl<T> SyncShared<T> {
+    pub fn new(value: T) -> Self {
+        SyncShared(Shared::new_in(value))
+    }
+}
+
+impl<T, B: Backend<T>> SyncShared<T, B> {
+    /// Allocates `value` with another lock, e.g.
+    /// `SyncShared::<_, ArcMutex>::new_in(value)`.
+    pub fn new_in(value: T) -> Self {
+        SyncShared(Shared::new_in(value))
+    }
+}
+
+impl<T: ?Sized, B: Backend<T>> SyncShared<T, B> {
+    pub fn into_shared(self) -> Shared<T, B> {
+        self.0
+    }
+}
+
+impl<T: ?Sized, B: Backend<T>> Deref for SyncShared<T, B> {
+    type Target = Shared<T, B>;
+
+    fn deref(&self) -> &Shared<T, B> {
+        &self.0
+    }
+}
+
+impl<T: ?Sized, B: Backend<T>> Clone for SyncShared<T, B> {
+    fn clone(&self) -> Self {
+        SyncShared(self.0.clone())
+    }
+}
+
+impl<T: Default, B: Backend<T>> Default for SyncShared<T, B> {
+    fn default() -> Self {
+        SyncShared(Shared::default())
+    }
+}
+
+impl<T: ?Sized, B: Backend<T>> PartialEq for SyncShared<T, B> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl<T: ?Sized, B: Backend<T>> Eq for SyncShared<T, B> { }
+
+impl<T: ?Sized, B: Backend<T>> Hash for SyncShared<T, B> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state)
+    }
+}
+
+impl<T: ?Sized, B: Backend<T>> fmt::Debug for SyncShared<T, B> where Shared<T, B>: fmt::Debug {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<T: ?Sized, B: Backend<T>> fmt::Display for SyncShared<T, B> where Shared<T, B>: fmt::Display {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<T> From<T> for SyncShared<T> {
+    fn from(value: T) -> Self {
+        SyncShared::new(value)
+    }
+}
+
+impl<T: ?Sized, B: Backend<T>> From<SyncShared<T, B>> for Shared<T, B> {
+    fn from(value: SyncShared<T, B>) -> Self {
+        value.0
+    }
+}
+
+/// `Arc<RwLock<T>>`, allowing concurrent readers.
+pub enum ArcRwLock {}
+
+/// `Arc<Mutex<T>>`, every access is exclusive.
+pub enum ArcMutex {}
+
+impl<T: ?Sized> Backend<T> for ArcRwLock {
+    type Data = Arc<RwLock<T>>;
+    type Ref<'a> = RwLockReadGuard<'a, T> where T: 'a;
+    type RefMut<'a> = RwLockWriteGuard<'a, T> where T: 'a;
+
+    pointer_backend!(Arc, RwLock);
+
+    fn borrow(data: &Self::Data) -> Option<Self::Ref<'_>> {
+        Some(data.read().unwrap_or_else(|err| err.into_inner()))
+    }
+
+    fn borrow_mut(data: &Self::Data) -> Option<Self::RefMut<'_>> {
+        Some(data.write().unwrap_or_else(|err| err.into_inner()))
+    }
+
+    fn try_borrow(data: &Self::Data) -> Option<Self::Ref<'_>> {
+        match data.try_read() {
+            Ok(guard) => Some(guard),
+            Err(TryLockError::Poisoned(err)) => Some(err.into_inner()),
+            Err(TryLockError::WouldBlock) => None,
+        }
+    }
+
+    fn try_borrow_mut(data: &Self::Data) -> Option<Self::RefMut<'_>> {
+        match data.try_write() {
+            Ok(guard) => Some(guard),
+            Err(TryLockError::Poisoned(err)) => Some(err.into_inner()),
+            Err(TryLockError::WouldBlock) => None,
+        }
+    }
+
+    fn try_unwrap(data: Self::Data) -> Result<T, Self::Data> where T: Sized {
+        Arc::try_unwrap(data).map(|lock| lock.into_inner().unwrap_or_else(|err| err.into_inner()))
+    }
+
+    fn into_inner(data: Self::Data) -> Option<T> where T: Sized {
+        Arc::into_inner(data).map(|lock| lock.into_inner().unwrap_or_else(|err| err.into_inner()))
+    }
+
+    fn get_mut(data: &mut Self::Data) -> Option<&mut T> {
+        Arc::get_mut(data).map(|lock| lock.get_mut().unwrap_or_else(|err| err.into_inner()))
+    }
+}
+
+impl<T: ?Sized> WeakBackend<T> for ArcRwLock {
+    pointer_backend!(weak Arc, Weak, RwLock);
+}
+
+impl<T: ?Sized> PointerBackend<T> for ArcRwLock {
+    pointer_backend!(raw Arc, RwLock);
+}
+
+impl<T: ?Sized> Backend<T> for ArcMutex {
+    type Data = Arc<Mutex<T>>;
+    type Ref<'a> = MutexGuard<'a, T> where T: 'a;
+    type RefMut<'a> = MutexGuard<'a, T> where T: 'a;
+
+    pointer_backend!(Arc, Mutex);
+
+    fn borrow(data: &Self::Data) -> Option<Self::Ref<'_>> {
+        Self::borrow_mut(data)
+    }
+
+    fn borrow_mut(data: &Self::Data) -> Option<Self::RefMut<'_>> {
+        Some(data.lock().unwrap_or_else(|err| err.into_inner()))
+    }
+
+    fn try_borrow(data: &Self::Data) -> Option<Self::Ref<'_>> {
+        Self::try_borrow_mut(data)
+    }
+
+    fn try_borrow_mut(data: &Self::Data) -> Option<Self::RefMut<'_>> {
+        match data.try_lock() {
+            Ok(guard) => Some(guard),
+            Err(TryLockError::Poisoned(err)) => Some(err.into_inner()),
+            Err(TryLockError::WouldBlock) => None,
+        }
+    }
+
+    fn try_unwrap(data: Self::Data) -> Result<T, Self::Data> where T: Sized {
+        Arc::try_unwrap(data).map(|lock| lock.into_inner().unwrap_or_else(|err| err.into_inner()))
+    }
+
+    fn into_inner(data: Self::Data) -> Option<T> where T: Sized {
+        Arc::into_inner(data).map(|lock| lock.into_inner().unwrap_or_else(|err| err.into_inner()))
+    }
+
+    fn get_mut(data: &mut Self::Data) -> Option<&mut T> {
+        Arc::get_mut(data).map(|lock| lock.get_mut().unwrap_or_else(|err| err.into_inner()))
+    }
+}
+
+impl<T: ?Sized> WeakBackend<T> for ArcMutex {
+    pointer_backend!(weak Arc, Weak, Mutex);
+}
+
+impl<T: ?Sized> PointerBackend<T> for ArcMutex {
+    pointer_backend!(raw Arc, Mutex);
+}
+
+impl<T: Send> Shared<T> {
+    /// Moves the value of a unique handle into a `SyncShared`, or gives the
+    /// handle back. Weak handles to it stop upgrading.
+    pub fn try_into_sync(self) -> Result<SyncShared<T>, Self> {
+        Shared::try_unwrap(self).map(SyncShared::new)
+    }
+}
+
+impl<T: ?Sized> From<Arc<RwLock<T>>> for Shared<T, ArcRwLock> {
+    fn from(data: Arc<RwLock<T>>) -> Self {
+        Shared::from_data(data)
+    }
+}
+
+impl<T: ?Sized> From<Arc<Mutex<T>>> for Shared<T, ArcMutex> {
+    fn from(data: Arc<Mutex<T>>) -> Self {
+        Shared::from_data(data)
+    }
+}
+
+/// Creates a `SyncShared` backed by `ArcRwLock`, with the same forms as
+/// [`shared!`](crate::shared).
+///
+/// ```
+/// let scores = shared::sync_shared!{"a" => 1.5, "b" => 2.0};
+/// let names = shared::sync_shared!["a", "b"];
+/// assert_eq!(scores.borrow()[names.borrow()[1]], 2.0);
+/// ```
+#[macro_export]
+macro_rules! sync_shared {
+    ($($key:expr => $value:expr),+ $(,)?) => {
+        $crate::SyncShared::new({
+            let mut map = $crate::__private::Map::new();
+            $(map.insert($key, $value);)+
+            map
+        })
+    };
+    ($value:expr; $n:expr) => {
+        $crate::SyncShared::new($crate::__private::vec![$value; $n])
+    };
+    ($value:expr) => {
+        $crate::SyncShared::new($value)
+    };
+    ($($values:expr),+ $(,)?) => {
+        $crate::SyncShared::new($crate::__private::vec![$($values),+])
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sync::{ArcMutex, SyncShared};
+    use crate::WeakShared;
+    use std::thread;
+
+    #[test]
+    fn it_works() {
+        let a: SyncShared<i32> = sync_shared!(12);
+        let b = a.clone();
+
+        *b.borrow_mut() += 1;
+        assert_eq!(*a.borrow(), 13);
+        assert_eq!(a.use_count(), 2);
+        assert_eq!(a, b);
+        assert_ne!(a, SyncShared::new(13));
+    }
+
+    #[test]
+    fn send_sync() {
+        fn assert_send_sync<T: Send + Sync>(_: &T) { }
+        assert_send_sync(&SyncShared::new(1));
+        assert_send_sync(&SyncShared::<_, ArcMutex>::new_in(1));
+    }
+
+    #[test]
+    fn macro_forms() {
+        let a = sync_shared!(12);
+        let list = sync_shared![1, 2];
+        let repeat = sync_shared![0; 3];
+        let map = sync_shared!{"a" => 1};
+        assert_eq!(*a.borrow(), 12);
+        assert_eq!(*list.borrow(), [1, 2]);
+        assert_eq!(*repeat.borrow(), [0, 0, 0]);
+        assert_eq!(map.borrow()["a"], 1);
+        thread::spawn(move || *a.borrow_mut() += 1).join().unwrap();
+    }
+
+    #[test]
+    fn guards() {
+        let a = SyncShared::new(1);
+        {
+            let _x = a.borrow();
+            assert!(a.try_borrow().is_ok());
+            assert!(a.try_borrow_mut().is_err());
+        }
+        let m = SyncShared::<_, ArcMutex>::new_in(1);
+        let _x = m.borrow();
+        assert!(m.try_borrow().is_err());
+    }
+
+    #[test]
+    fn threads() {
+        let counter = SyncShared::<usize, ArcMutex>::default();
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let counter = counter.clone();
+                thread::spawn(move || {
+                    for _ in 0..100 {
+                        *counter.borrow_mut() += 1;
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(*counter.borrow(), 400);
+    }
+
+    #[test]
+    fn weak() {
+        let a = SyncShared::from(1);
+        let weak: WeakShared<_, _> = a.downgrade();
+
+        let handle = thread::spawn(move || *weak.upgrade().unwrap().borrow());
+        assert_eq!(handle.join().unwrap(), 1);
+    }
+
+    #[test]
+    fn into_sync() {
+        let a = crate::Shared::new(vec![1]);
+        let b = a.clone();
+        let a = a.try_into_sync().unwrap_err();
+        drop(b);
+
+        let a = a.try_into_sync().unwrap();
+        thread::spawn(move || a.borrow_mut().push(2)).join().unwrap();
+    }
+}
@@ -0,0 +1,221 @@
+//! Unsized `Shared` values: trait objects, slices and strings.
+//!
+//! With the `nightly` feature handles coerce implicitly, like `Rc` does:
+//! `let w: Shared<dyn Widget> = Shared::new(Button)`. On stable use
+//! [`into_dyn!`](crate::into_dyn) for trait objects, and `Shared::from_vec`,
+//! `from_slice` or `from_str` for slices and strings.
+
+use alloc::alloc::Layout;
+use core::cell::RefCell;
+use core::mem::{self, ManuallyDrop};
+use core::ptr;
+use alloc::borrow::ToOwned;
+use alloc::boxed::Box;
+use alloc::rc::Rc;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::Shared;
+
+#[cfg(feature = "nightly")]
+mod coerce {
+    use core::marker::Unsize;
+    use core::ops::CoerceUnsized;
+
+    #[cfg(feature = "std")]
+    use crate::sync::{ArcMutex, ArcRwLock};
+    use crate::{RcRefCell, Shared};
+
+    impl<T: ?Sized + Unsize<U>, U: ?Sized> CoerceUnsized<Shared<U, RcRefCell>> for Shared<T, RcRefCell> {}
+
+    #[cfg(feature = "std")]
+    impl<T: ?Sized + Unsize<U>, U: ?Sized> CoerceUnsized<Shared<U, ArcMutex>> for Shared<T, ArcMutex> {}
+
+    #[cfg(feature = "std")]
+    impl<T: ?Sized + Unsize<U>, U: ?Sized> CoerceUnsized<Shared<U, ArcRwLock>> for Shared<T, ArcRwLock> {}
+}
+
+/// Converts a sized `Shared` into an unsized one, e.g. a trait object,
+/// keeping the allocation.
+///
+/// The backend defaults to `RcRefCell` and can be given as a second argument.
+///
+/// ```
+/// use shared::{into_dyn, Shared};
+/// use core::fmt::Display;
+///
+/// let value = Shared::new(12);
+/// let display = into_dyn!(value.clone() => dyn Display);
+/// assert_eq!(display.to_string(), "12");
+/// assert_eq!(value.use_count(), 2);
+/// ```
+#[macro_export]
+macro_rules! into_dyn {
+    ($shared:expr => $ty:ty) => {
+        $crate::into_dyn!($shared => $ty, $crate::RcRefCell)
+    };
+    ($shared:expr => $ty:ty, $backend:ty) => {
+        {
+            let data = $crate::Shared::<_, $backend>::into_data($shared);
+            let data: <$backend as $crate::Backend<$ty>>::Data = data;
+            $crate::Shared::<$ty, $backend>::from_data(data)
+        }
+    };
+}
+
+// `RefCell<[T]>` cannot be built from a runtime-sized buffer through the
+// std API, so the cell is laid out by hand in an `Rc` allocation of units
+// sized and aligned like the cell's alignment. Unsizing `RefCell<[T; N]>`
+// to `RefCell<[T]>` is valid for every `N`, which pins the header (the
+// borrow flag) and the offset of the tail to those of `RefCell<[T; 0]>`.
+fn slice_cell<T>(values: Vec<T>) -> Rc<RefCell<[T]>> {
+    #[repr(C)]
+    struct Unit<T>([T; 0], usize);
+
+    let mut values = ManuallyDrop::new(values);
+    let len = values.len();
+
+    let empty = RefCell::new([] as [T; 0]);
+    let offset = empty.as_ptr() as usize - &empty as *const _ as usize;
+    let align = mem::align_of::<RefCell<[T; 0]>>();
+    assert_eq!(mem::size_of::<Unit<T>>(), align);
+    let layout = Layout::from_size_align(offset + mem::size_of::<T>() * len, align)
+        .expect("slice is too large")
+        .pad_to_align();
+
+    let mut units = Rc::<[Unit<T>]>::new_uninit_slice(layout.size() / align);
+    unsafe {
+        let cell = Rc::get_mut(&mut units).unwrap().as_mut_ptr() as *mut u8;
+        ptr::copy_nonoverlapping(&empty as *const _ as *const u8, cell, offset);
+        ptr::copy_nonoverlapping(values.as_ptr(), cell.add(offset) as *mut T, len);
+        values.set_len(0);
+        ManuallyDrop::drop(&mut values);
+
+        // Same size and alignment as the units, so the `Rc` header and the
+        // deallocation layout are unchanged.
+        let cell = ptr::slice_from_raw_parts(Rc::into_raw(units) as *const T, len);
+        Rc::from_raw(cell as *const RefCell<[T]>)
+    }
+}
+
+impl<T> Shared<[T]> {
+    /// Moves the elements of `values` into a new shared slice.
+    pub fn from_vec(values: Vec<T>) -> Self {
+        Shared::from_data(slice_cell(values))
+    }
+
+    pub fn from_boxed_slice(values: Box<[T]>) -> Self {
+        Shared::from_vec(values.into_vec())
+    }
+
+    pub fn from_slice(values: &[T]) -> Self where T: Clone {
+        Shared::from_vec(values.to_vec())
+    }
+}
+
+impl Shared<str> {
+    pub fn from_string(value: String) -> Self {
+        let bytes = Rc::into_raw(slice_cell(value.into_bytes()));
+        // `RefCell<str>` has the same layout and metadata as `RefCell<[u8]>`.
+        Shared::from_data(unsafe { Rc::from_raw(bytes as *const RefCell<str>) })
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(value: &str) -> Self {
+        Shared::from_string(value.to_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Shared;
+    use std::rc::Rc;
+
+    trait Widget {
+        fn click(&mut self) -> usize;
+    }
+
+    struct Button {
+        clicks: usize,
+    }
+
+    impl Widget for Button {
+        fn click(&mut self) -> usize {
+            self.clicks += 1;
+            self.clicks
+        }
+    }
+
+    #[test]
+    fn into_dyn() {
+        let button = Shared::new(Button { clicks: 0 });
+        let mut widgets: Vec<Shared<dyn Widget>> = vec![
+            into_dyn!(button.clone() => dyn Widget),
+            into_dyn!(button.clone() => dyn Widget),
+        ];
+
+        for widget in &mut widgets {
+            widget.click();
+        }
+        assert_eq!(button.clicks, 2);
+        assert_eq!(button.use_count(), 3);
+
+        #[cfg(feature = "std")]
+        {
+            use crate::sync::ArcMutex;
+            use std::fmt::Debug;
+
+            let job = Shared::<_, ArcMutex>::new_in(vec![1]);
+            let job = into_dyn!(job => dyn Debug + Send, ArcMutex);
+            assert_eq!(format!("{:?}", &*job.borrow()), "[1]");
+        }
+    }
+
+    #[test]
+    fn slices() {
+        let mut a = Shared::from_vec(vec!["a".to_owned(), "b".to_owned()]);
+        let b = a.clone();
+
+        a[1].push('c');
+        assert_eq!(&*b, ["a", "bc"]);
+        assert_eq!(b.borrow().len(), 2);
+
+        let empty = Shared::<[()]>::from_vec(Vec::new());
+        assert!(empty.is_empty());
+        let wide = Shared::from_vec(vec![u128::MAX; 3]);
+        assert_eq!(wide[2], u128::MAX);
+
+        let boxed: Box<[u64]> = Box::new([1, 2, 3]);
+        assert_eq!(&*Shared::from_boxed_slice(boxed), [1, 2, 3]);
+    }
+
+    #[test]
+    fn slice_drops_elements() {
+        let item = Rc::new(());
+        let slice = Shared::from_slice(&[item.clone(), item.clone()]);
+
+        assert_eq!(Rc::strong_count(&item), 3);
+        drop(slice);
+        assert_eq!(Rc::strong_count(&item), 1);
+    }
+
+    #[test]
+    fn strings() {
+        let mut a = Shared::from_str("hello");
+        let b = a.clone();
+
+        a.make_ascii_uppercase();
+        assert_eq!(&*b, "HELLO");
+    }
+
+    #[test]
+    #[cfg(feature = "nightly")]
+    fn coerce() {
+        let button = Shared::new(Button { clicks: 0 });
+        let mut widget: Shared<dyn Widget> = button.clone();
+
+        assert_eq!(widget.click(), 1);
+        let array: Shared<[i32]> = Shared::new([1, 2, 3]);
+        assert_eq!(array.len(), 3);
+    }
+}
@@ -0,0 +1,323 @@
+//! Generation counters for `Shared` values.
+//!
+//! [`Versioned`] wraps another backend and stores a counter next to the
+//! value, in the same allocation. Every mutable access (`deref_mut`,
+//! `as_mut`, `borrow_mut`, `get_mut`, ...) increments it, so a
+//! [`VersionStamp`] taken earlier tells whether the value may have changed
+//! since, without comparing the values themselves.
+//!
+//! The counter is opt-in rather than part of every `Shared`: the default
+//! backend stays a plain `Rc<RefCell<T>>`, which `Shared::as_rc` and the
+//! `From<Rc<RefCell<T>>>` conversion rely on.
+
+use core::convert::Infallible;
+use core::fmt;
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
+use core::ptr;
+#[cfg(target_has_atomic = "64")]
+use core::sync::atomic::AtomicU64 as AtomicId;
+#[cfg(not(target_has_atomic = "64"))]
+use core::sync::atomic::AtomicUsize as AtomicId;
+use core::sync::atomic::Ordering;
+
+use crate::backend::{Backend, PointerBackend, RawBackend, WeakBackend};
+use crate::{BorrowError, RcRefCell, Shared};
+
+/// Backend adaptor counting mutable accesses, e.g. `Versioned<ArcRwLock>`.
+pub enum Versioned<B = RcRefCell> {
+    #[doc(hidden)]
+    __Never(Infallible, PhantomData<B>),
+}
+
+/// `Shared` with a version counter, see [`Shared::version`].
+pub type VersionedShared<T, B = RcRefCell> = Shared<T, Versioned<B>>;
+
+// Identifies allocations in stamps, addresses can be reused once freed.
+// Targets without 64-bit atomics count in `usize`.
+#[cfg(target_has_atomic = "64")]
+type Id = u64;
+#[cfg(not(target_has_atomic = "64"))]
+type Id = usize;
+
+static NEXT_ID: AtomicId = AtomicId::new(0);
+
+/// Value stored by the inner backend of [`Versioned`].
+pub struct VersionCell<T: ?Sized> {
+    id: Id,
+    version: u64,
+    value: T,
+}
+
+impl<T> VersionCell<T> {
+    fn new(value: T) -> Self {
+        VersionCell { id: NEXT_ID.fetch_add(1, Ordering::Relaxed), version: 0, value }
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for VersionCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VersionCell")
+            .field("version", &self.version)
+            .field("value", &&self.value)
+            .finish()
+    }
+}
+
+/// Guard of the inner backend, dereferencing to the value.
+pub struct VersionRef<G>(G);
+
+/// Mutable guard of the inner backend, taking it has bumped the version.
+pub struct VersionRefMut<G>(G);
+
+impl<T: ?Sized, G: Deref<Target = VersionCell<T>>> Deref for VersionRef<G> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0.value
+    }
+}
+
+impl<T: ?Sized, G: Deref<Target = VersionCell<T>>> Deref for VersionRefMut<G> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0.value
+    }
+}
+
+impl<T: ?Sized, G: DerefMut<Target = VersionCell<T>>> DerefMut for VersionRefMut<G> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0.value
+    }
+}
+
+impl<T: ?Sized, B: Backend<VersionCell<T>>> Backend<T> for Versioned<B> {
+    type Data = B::Data;
+    type Ref<'a> = VersionRef<B::Ref<'a>> where Self: 'a, T: 'a;
+    type RefMut<'a> = VersionRefMut<B::RefMut<'a>> where Self: 'a, T: 'a;
+
+    fn new(value: T) -> Self::Data where T: Sized {
+        B::new(VersionCell::new(value))
+    }
+
+    fn strong_count(data: &Self::Data) -> usize {
+        B::strong_count(data)
+    }
+
+    fn addr(data: &Self::Data) -> *const u8 {
+        B::addr(data)
+    }
+
+    fn borrow(data: &Self::Data) -> Option<Self::Ref<'_>> {
+        B::borrow(data).map(VersionRef)
+    }
+
+    fn borrow_mut(data: &Self::Data) -> Option<Self::RefMut<'_>> {
+        B::borrow_mut(data).map(bump)
+    }
+
+    fn try_borrow(data: &Self::Data) -> Option<Self::Ref<'_>> {
+        B::try_borrow(data).map(VersionRef)
+    }
+
+    fn try_borrow_mut(data: &Self::Data) -> Option<Self::RefMut<'_>> {
+        B::try_borrow_mut(data).map(bump)
+    }
+
+    fn try_unwrap(data: Self::Data) -> Result<T, Self::Data> where T: Sized {
+        B::try_unwrap(data).map(|cell| cell.value)
+    }
+
+    fn into_inner(data: Self::Data) -> Option<T> where T: Sized {
+        B::into_inner(data).map(|cell| cell.value)
+    }
+
+    fn get_mut(data: &mut Self::Data) -> Option<&mut T> {
+        let cell = B::get_mut(data)?;
+        cell.version += 1;
+        Some(&mut cell.value)
+    }
+
+    #[cfg(feature = "serde")]
+    fn try_new_cyclic_any<E>(
+        f: impl FnOnce(Option<alloc::boxed::Box<dyn core::any::Any>>) -> Result<T, E>,
+    ) -> Result<Self::Data, E>
+    where
+        T: Sized + 'static,
+    {
+        B::try_new_cyclic_any(|weak| f(weak).map(VersionCell::new))
+    }
+}
+
+fn bump<T: ?Sized, G: DerefMut<Target = VersionCell<T>>>(mut guard: G) -> VersionRefMut<G> {
+    guard.version += 1;
+    VersionRefMut(guard)
+}
+
+unsafe impl<T: ?Sized, B: RawBackend<VersionCell<T>>> RawBackend<T> for Versioned<B> {
+    fn as_ptr(data: &Self::Data) -> *mut T {
+        unsafe { ptr::addr_of_mut!((*B::as_ptr(data)).value) }
+    }
+
+    fn on_deref(data: &Self::Data) -> bool {
+        B::on_deref(data)
+    }
+
+    // `DerefMut` hands out `&mut T`, which is when the value may change.
+    fn on_deref_mut(data: &Self::Data) -> bool {
+        if !B::on_deref_mut(data) {
+            return false;
+        }
+        unsafe { (*B::as_ptr(data)).version += 1 };
+        true
+    }
+}
+
+impl<T: ?Sized, B: WeakBackend<VersionCell<T>>> WeakBackend<T> for Versioned<B> {
+    type Weak = B::Weak;
+
+    fn dangling() -> Self::Weak where T: Sized {
+        B::dangling()
+    }
+
+    fn downgrade(data: &Self::Data) -> Self::Weak {
+        B::downgrade(data)
+    }
+
+    fn upgrade(weak: &Self::Weak) -> Option<Self::Data> {
+        B::upgrade(weak)
+    }
+
+    fn weak_count(data: &Self::Data) -> usize {
+        B::weak_count(data)
+    }
+
+    fn weak_strong_count(weak: &Self::Weak) -> usize {
+        B::weak_strong_count(weak)
+    }
+
+    fn weak_weak_count(weak: &Self::Weak) -> usize {
+        B::weak_weak_count(weak)
+    }
+
+    fn weak_ptr_eq(a: &Self::Weak, b: &Self::Weak) -> bool {
+        B::weak_ptr_eq(a, b)
+    }
+
+    fn new_cyclic(f: impl FnOnce(&Self::Weak) -> T) -> Self::Data where T: Sized {
+        B::new_cyclic(|weak| VersionCell::new(f(weak)))
+    }
+
+    fn try_new_cyclic<E>(f: impl FnOnce(&Self::Weak) -> Result<T, E>) -> Result<Self::Data, E>
+    where
+        T: Sized,
+    {
+        B::try_new_cyclic(|weak| f(weak).map(VersionCell::new))
+    }
+}
+
+impl<T: ?Sized, B: PointerBackend<VersionCell<T>>> PointerBackend<T> for Versioned<B> {
+    type Raw = B::Raw;
+
+    fn into_raw(data: Self::Data) -> *const Self::Raw {
+        B::into_raw(data)
+    }
+
+    unsafe fn from_raw(ptr: *const Self::Raw) -> Self::Data {
+        B::from_raw(ptr)
+    }
+}
+
+/// Version of an allocation at some point, see [`Shared::version`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct VersionStamp {
+    id: Id,
+    version: u64,
+}
+
+impl VersionStamp {
+    /// Number of mutable accesses to the allocation when it was taken.
+    pub fn get(self) -> u64 {
+        self.version
+    }
+}
+
+impl<T: ?Sized, B: Backend<VersionCell<T>>> Shared<T, Versioned<B>> {
+    /// Current version, briefly borrowing the value. Stamps of different
+    /// allocations never compare equal, even if one reuses the address of
+    /// another that was freed.
+    #[track_caller]
+    pub fn version(this: &Self) -> VersionStamp {
+        match B::borrow(&this.data) {
+            Some(cell) => VersionStamp { id: cell.id, version: cell.version },
+            None => this.conflict(BorrowError::new()),
+        }
+    }
+
+    /// Whether the value may have changed since `stamp` was taken.
+    #[track_caller]
+    pub fn changed_since(this: &Self, stamp: VersionStamp) -> bool {
+        Shared::version(this) != stamp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::version::VersionedShared;
+    use crate::Shared;
+
+    #[test]
+    fn version() {
+        let mut a = VersionedShared::<_>::new_in(vec![1]);
+        let b = a.clone();
+        let stamp = Shared::version(&a);
+
+        assert_eq!(a.len() + b.borrow().len(), 2);
+        assert!(!Shared::changed_since(&b, stamp));
+
+        a.push(2);
+        assert!(Shared::changed_since(&b, stamp));
+        let stamp = Shared::version(&b);
+        a.as_mut().push(3);
+        b.borrow_mut().push(4);
+        assert_eq!(Shared::version(&a).get(), stamp.get() + 2);
+        assert_eq!(*a, [1, 2, 3, 4]);
+
+        let c = VersionedShared::<_>::new_in(vec![1]);
+        assert_ne!(Shared::version(&c), Shared::version(&VersionedShared::<_>::new_in(vec![1])));
+    }
+
+    #[test]
+    fn reused_address() {
+        let stamp = Shared::version(&VersionedShared::<_>::new_in(1));
+        let a = VersionedShared::<_>::new_in(1);
+        assert!(Shared::changed_since(&a, stamp));
+    }
+
+    #[test]
+    fn unique_access() {
+        let mut a = VersionedShared::<_>::new_in(1);
+        let stamp = Shared::version(&a);
+
+        *Shared::get_mut(&mut a).unwrap() += 1;
+        assert!(Shared::changed_since(&a, stamp));
+        assert_eq!(Shared::try_unwrap(a).unwrap(), 2);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn sync() {
+        use crate::sync::ArcRwLock;
+        use crate::version::Versioned;
+
+        let a = Shared::<_, Versioned<ArcRwLock>>::new_in(String::new());
+        let stamp = Shared::version(&a);
+
+        let _ = a.borrow().len();
+        assert!(!Shared::changed_since(&a, stamp));
+        a.borrow_mut().push('a');
+        assert_eq!(Shared::version(&a).get(), 1);
+        assert_eq!(a.downgrade().upgrade(), Some(a));
+    }
+}
@@ -0,0 +1,138 @@
+use core::fmt;
+
+use crate::backend::{RcRefCell, WeakBackend};
+use crate::Shared;
+
+/// Non-owning handle to a `Shared` allocation.
+pub struct WeakShared<T: ?Sized, B: WeakBackend<T> = RcRefCell> {
+    data: B::Weak,
+}
+
+impl<T, B: WeakBackend<T>> WeakShared<T, B> {
+    /// Creates a handle that never upgrades.
+    pub fn new() -> Self {
+        WeakShared { data: B::dangling() }
+    }
+}
+
+impl<T: ?Sized, B: WeakBackend<T>> WeakShared<T, B> {
+    pub(crate) fn from_weak(data: B::Weak) -> Self {
+        WeakShared { data }
+    }
+
+    pub fn upgrade(&self) -> Option<Shared<T, B>> {
+        B::upgrade(&self.data).map(Shared::from_data)
+    }
+
+    pub fn use_count(&self) -> usize {
+        B::weak_strong_count(&self.data)
+    }
+
+    pub fn weak_count(&self) -> usize {
+        B::weak_weak_count(&self.data)
+    }
+
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        B::weak_ptr_eq(&self.data, &other.data)
+    }
+}
+
+impl<T> Shared<T> {
+    /// Builds a value holding weak handles to itself, like `Rc::new_cyclic`.
+    /// The handle passed to `f` does not upgrade until `new_cyclic` returns.
+    pub fn new_cyclic(f: impl FnOnce(&WeakShared<T>) -> T) -> Self {
+        Shared::from_data(RcRefCell::new_cyclic(|weak| f(&WeakShared::from_weak(weak.clone()))))
+    }
+}
+
+impl<T: ?Sized, B: WeakBackend<T>> Shared<T, B> {
+    pub fn downgrade(&self) -> WeakShared<T, B> {
+        WeakShared { data: B::downgrade(&self.data) }
+    }
+
+    pub fn weak_count(&self) -> usize {
+        B::weak_count(&self.data)
+    }
+}
+
+impl<T: ?Sized, B: WeakBackend<T>> Clone for WeakShared<T, B> {
+    fn clone(&self) -> Self {
+        WeakShared { data: self.data.clone() }
+    }
+}
+
+impl<T, B: WeakBackend<T>> Default for WeakShared<T, B> {
+    fn default() -> Self {
+        WeakShared::new()
+    }
+}
+
+impl<T: ?Sized, B: WeakBackend<T>> PartialEq for WeakShared<T, B> {
+    fn eq(&self, other: &Self) -> bool {
+        self.ptr_eq(other)
+    }
+}
+
+impl<T: ?Sized, B: WeakBackend<T>> Eq for WeakShared<T, B> { }
+
+impl<T: ?Sized, B: WeakBackend<T>> fmt::Debug for WeakShared<T, B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("WeakShared { .. }")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Shared, WeakShared};
+
+    #[test]
+    fn upgrade_downgrade() {
+        let a = Shared::new(12);
+        let weak = a.downgrade();
+
+        assert_eq!(a.weak_count(), 1);
+        assert_eq!(weak.use_count(), 1);
+        assert_eq!(weak.upgrade(), Some(a.clone()));
+
+        drop(a);
+        assert_eq!(weak.upgrade(), None);
+        assert_eq!(weak.use_count(), 0);
+    }
+
+    #[test]
+    fn dangling() {
+        let weak = WeakShared::<i32>::new();
+
+        assert_eq!(weak.upgrade(), None);
+        assert_eq!(weak.weak_count(), 0);
+        assert!(weak.ptr_eq(&WeakShared::default()));
+    }
+
+    #[test]
+    fn identity() {
+        let a = Shared::new(1);
+        let b = Shared::new(1);
+
+        assert_eq!(a.downgrade(), a.clone().downgrade());
+        assert_ne!(a.downgrade(), b.downgrade());
+    }
+
+    #[test]
+    fn new_cyclic() {
+        #[derive(Debug)]
+        struct Node {
+            parent: WeakShared<Node>,
+            children: Vec<Shared<Node>>,
+        }
+
+        let root = Shared::new_cyclic(|root: &WeakShared<Node>| {
+            assert_eq!(root.upgrade(), None);
+            let child = Shared::new(Node { parent: root.clone(), children: Vec::new() });
+            Node { parent: WeakShared::new(), children: vec![child] }
+        });
+
+        assert_eq!(root.children[0].parent.upgrade(), Some(root.clone()));
+        assert_eq!(root.parent.upgrade(), None);
+        assert_eq!(root.weak_count(), 1);
+    }
+}
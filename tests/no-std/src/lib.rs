@@ -0,0 +1,36 @@
+//! Built by `tests/no_std.rs` for a bare-metal target to check that `shared`
+//! compiles against `core` and `alloc` only.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+
+use shared::{shared, Shared};
+
+struct Sink(usize);
+
+impl Write for Sink {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0 += s.len();
+        Ok(())
+    }
+}
+
+pub fn check() -> usize {
+    let a: Shared<u32> = Shared::from(1);
+    let b = a.clone();
+    *b.borrow_mut() += 1;
+
+    let list = shared![1, 2, 3];
+    let repeat = shared![0u8; 4];
+    let map = shared! {"a" => 1};
+    let default = Shared::<Vec<u8>>::default();
+
+    let mut sink = Sink(0);
+    let _ = write!(sink, "{:?}", a);
+    let value = *a.borrow() as usize;
+    value + list.len() + repeat.len() + map.len() + default.len() + sink.0
+}
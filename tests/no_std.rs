@@ -0,0 +1,28 @@
+use std::env;
+use std::path::Path;
+use std::process::Command;
+
+const TARGET: &str = "thumbv7em-none-eabihf";
+
+#[test]
+fn builds_without_std() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+
+    // A host build links `std` anyway and would prove nothing.
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_owned());
+    let sysroot = Command::new(rustc).args(["--print", "sysroot"]).output().unwrap();
+    let sysroot = String::from_utf8(sysroot.stdout).unwrap();
+    assert!(
+        Path::new(sysroot.trim()).join("lib/rustlib").join(TARGET).exists(),
+        "target `{0}` is missing, run `rustup target add {0}`",
+        TARGET,
+    );
+
+    let status = Command::new(env!("CARGO"))
+        .args(["build", "--quiet", "--package", "shared-no-std-check", "--target", TARGET])
+        .current_dir(root)
+        .env("CARGO_TARGET_DIR", root.join("target/no-std"))
+        .status()
+        .unwrap();
+    assert!(status.success());
+}